@@ -0,0 +1,62 @@
+extern crate neat;
+extern crate rand;
+
+use std::env;
+use std::process;
+
+use rand::{SeedableRng, StdRng};
+
+use neat::exp;
+use neat::exp::registry;
+use neat::eval;
+
+/// The number at position `index` of the arguments, or `default` if there are fewer arguments
+fn number_arg(args: &[String], index: usize, name: &str, default: usize) -> usize {
+    match args.get(index) {
+        Some(arg) => match arg.parse::<usize>() {
+            Ok(value) => value,
+            Err(err) => {
+                eprintln!("Invalid {} {}: {}", name, arg, err);
+                process::exit(1);
+            }
+        },
+        None => default
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    let num_runs = number_arg(&args, 1, "number of runs", 100);
+
+    // The benchmark is reproducible for a fixed seed
+    let seed = number_arg(&args, 2, "seed", 0);
+
+    // The remaining arguments name the experiments to benchmark
+    let names = if args.len() > 3 {
+        args[3..].iter().map(|name| name.as_ref()).collect()
+    } else {
+        vec!["xor"]
+    };
+
+    let evaluator = eval::Evaluator::new(1);
+
+    for name in names {
+        let entry = match registry::find(name) {
+            Some(entry) => entry,
+            None => {
+                eprintln!("Unknown experiment {}, choose from {}", name, registry::names().join(", "));
+                process::exit(1);
+            }
+        };
+
+        let mut rng: StdRng = SeedableRng::from_seed(&[seed][..]);
+        match exp::benchmark(&entry.new_experiment(), &evaluator, &mut rng, 150, 100, num_runs) {
+            Ok(result) => println!("{}: {}", entry.name, result),
+            Err(err) => {
+                eprintln!("{}: {}", entry.name, err);
+                process::exit(1);
+            }
+        }
+    }
+}
@@ -1,167 +1,293 @@
+extern crate getopts;
 extern crate neat;
 extern crate rand;
 
-use std::io::prelude::*;
-use std::fs::File;
-use std::path::Path;
-use std::thread;
-use std::sync::Arc;
-use std::sync::mpsc::channel;
-use std::sync::mpsc::Receiver;
-use std::sync::mpsc::Sender;
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
 
-use neat::genes;
+use getopts::Options;
+use rand::{Rng, SeedableRng, StdRng};
+
+use neat::config;
 use neat::exp;
+use neat::exp::registry;
+use neat::eval;
 use neat::pop;
-use neat::mutation;
-use neat::exp::Experiment;
+use neat::stats;
+
+struct RunOptions {
+    population_size: usize,
+    max_generations: usize,
+    target_fitness: Option<f64>,
+    num_threads: usize,
+    seed: Option<usize>, // Random if not given, taken from the checkpoint when resuming
+    output_dir: PathBuf,
+    checkpoint_interval: usize, // Zero for no checkpoints
+    resume: Option<PathBuf>,
+    config_file: Option<PathBuf>,
+    settings: Vec<String>, // Single settings changed on the command line, e.g. mutation.new_node_prob=0.03
+    verbose: bool, // Print a summary of each generation to stdout
+}
+
+fn fail<E: Display>(message: &str, err: E) -> ! {
+    eprintln!("{}: {}", message, err);
+    process::exit(1);
+}
+
+fn parse_opt<T: std::str::FromStr>(matches: &getopts::Matches, name: &str) -> Option<T> {
+    matches.opt_str(name).map(|value| match value.parse::<T>() {
+        Ok(value) => value,
+        Err(_) => fail(&format!("Invalid value for --{}", name), value)
+    })
+}
+
+/// Every generation has its own stream of random numbers, so that a run resumed from a checkpoint
+/// continues exactly like the original run
+fn generation_rng(seed: usize, generation: usize) -> StdRng {
+    SeedableRng::from_seed(&[seed, generation][..])
+}
 
-/*fn to_f(x: bool) -> f64 {
-    if x { 1.0 } else { -1.0 }
+fn evaluate<E: exp::Experiment + Send + Sync + 'static>(evaluator: &eval::Evaluator,
+                                                        rng: &mut StdRng,
+                                                        experiment: &mut E,
+                                                        population: &mut pop::Population) {
+    for failure in evaluator.evaluate(rng, experiment, population) {
+        eprintln!("Evaluation of organism {} in species {} failed: {}",
+                  failure.organism_index, failure.species_id, failure.message);
+    }
 }
 
-fn evaluate(organism: &mut pop::Organism, print: bool) {
-    let fitness = {
-        let mut error = |x: bool, y: bool| -> f64 {
-            organism.network.flush();
-            organism.network.set_input(&vec![(0, to_f(x)), (1, to_f(y))]);
-            for _ in 1..10 { organism.network.activate(); }
+fn run<E: exp::Experiment + Send + Sync + 'static>(mut experiment: E,
+                                                   default_config: config::Config,
+                                                   options: &RunOptions) {
+    let output_dir = &options.output_dir;
+    let evaluator = eval::Evaluator::new(options.num_threads);
 
-            let output = organism.network.get_output()[0].1;
-            let expected_output = to_f(x != y);
+    for dir in ["best", "dot"].iter() {
+        if let Err(err) = fs::create_dir_all(output_dir.join(dir)) {
+            fail(&format!("Cannot create {}", output_dir.join(dir).display()), err);
+        }
+    }
+
+    // Resume from a checkpoint if one is given. The checkpoint contains the settings.
+    let (mut population, seed, mut rng) = match options.resume {
+        Some(ref path) => match pop::Checkpoint::load(path) {
+            Ok(checkpoint) => {
+                let seed = checkpoint.seed;
+                match options.seed {
+                    Some(given) if given != seed =>
+                        fail("Invalid arguments", format!("the checkpoint was written by a run with seed {}", seed)),
+                    _ => ()
+                }
+
+                if let Err(err) = experiment.load_state(&checkpoint.experiment_state) {
+                    fail(&format!("Cannot restore experiment from checkpoint {}", path.display()), err);
+                }
+
+                let rng = generation_rng(seed, checkpoint.population.generation);
+                (checkpoint.population, seed, rng)
+            },
+            Err(err) => fail(&format!("Cannot load checkpoint {}", path.display()), err)
+        },
+        None => {
+            let mut config = default_config;
+
+            if let Some(ref path) = options.config_file {
+                if let Err(err) = config.merge_file(path) {
+                    fail(&format!("Cannot load config {}", path.display()), err);
+                }
+            }
+            for setting in options.settings.iter() {
+                if let Err(err) = config.set(setting) {
+                    fail(&format!("Invalid setting {}", setting), err);
+                }
+            }
 
-            if (print) {
-                println!("{},{} -> {} vs {}", x, y, output, expected_output);
+            let invalid = config.validate(options.population_size);
+            if !invalid.is_empty() {
+                eprintln!("Cannot run experiment with invalid settings:");
+                for setting in invalid.iter() {
+                    eprintln!("  {}", setting);
+                }
+                process::exit(1);
             }
 
-            (output - expected_output).abs() / 2.0
-        };
+            // Record the settings in force, so that the run can be repeated
+            if let Err(err) = config.save(&output_dir.join("config.json")) {
+                fail("Cannot write config", err);
+            }
 
-        let sum_error = error(false, false) +
-                        error(false, true) +
-                        error(true, false) +
-                        error(true, true);
-        (4.0 - sum_error).powf(2.0)
+            let seed = options.seed.unwrap_or_else(|| rand::thread_rng().gen::<usize>());
+            let mut rng = generation_rng(seed, 0);
+            let population = pop::Population::from_initial_genome(&mut rng,
+                                                                  &config.population,
+                                                                  &config.mutation,
+                                                                  &config.compat,
+                                                                  &experiment.initial_genome(),
+                                                                  options.population_size);
+            match population {
+                Ok(population) => (population, seed, rng),
+                Err(err) => fail("Cannot run experiment", err)
+            }
+        }
     };
 
-    organism.fitness = fitness;
-} */
+    // A run can only be reproduced with its seed
+    println!("Seed: {}", seed);
 
-fn evaluate<E: exp::Experiment + Send + Sync + Clone + 'static>(experiment: &mut E, population: &mut pop::Population) {
-    //evaluate_single_threaded(experiment, population);
-    let shared_experiment = Arc::new(experiment.clone());
-    
-    evaluate_multi_threaded(shared_experiment, population);
+    // A resumed run repeats the generations after its checkpoint, so their rows are replaced
+    let log_mode = if options.resume.is_some() {
+        stats::LogMode::Resume(population.generation)
+    } else {
+        stats::LogMode::New
+    };
+    let mut observers: Vec<Box<stats::Observer>> = vec![];
+    if options.verbose {
+        observers.push(Box::new(stats::Printer));
+    }
 
-    experiment.post_evaluation(&population);
-}
+    match stats::CsvLog::open(&output_dir.join("log.csv"), &output_dir.join("species.csv"), log_mode) {
+        Ok(log) => observers.push(Box::new(log)),
+        Err(err) => fail("Cannot open CSV log", err)
+    }
+    match stats::JsonLog::open(&output_dir.join("log.jsonl"), &output_dir.join("species.jsonl"), log_mode) {
+        Ok(log) => observers.push(Box::new(log)),
+        Err(err) => fail("Cannot open JSON log", err)
+    }
+
+    loop {
+        let generation = population.generation;
 
-fn evaluate_single_threaded<E: exp::Experiment + Send + Sync + 'static>(experiment: Arc<E>, population: &mut pop::Population) {
-    for species in population.species.iter_mut() {
-        for organism in species.organisms.iter_mut() {
-            let fitness = 0.0; //experiment.evaluate(&mut organism.network);
-            organism.fitness = fitness;
+        // Checkpoints are written before evaluation, so that resuming repeats it with the same numbers
+        if options.checkpoint_interval > 0 && generation > 0 && generation % options.checkpoint_interval == 0 {
+            if let Err(err) = population.save(&output_dir.join("checkpoint.json"), seed, experiment.save_state()) {
+                fail("Cannot write checkpoint", err);
+            }
         }
-    }
-}
 
-fn evaluate_multi_threaded<E: exp::Experiment + Send + Sync + 'static>(experiment: Arc<E>, population: &mut pop::Population) {
-    let num_threads = 8;
-    let num_population = population.num_organisms();
+        evaluate(&evaluator, &mut rng, &mut experiment, &mut population);
 
-    let num_tasks_per_thread = num_population / num_threads;
+        let best = population.best_organism().map(|organism| organism.clone());
 
-    let mut organism_indices = vec![];
-    let mut organisms = vec![];
+        if let Some(ref best) = best {
+            let genome_path = output_dir.join("best").join(format!("{}.json", generation));
+            if let Err(err) = best.genome.save(&genome_path) {
+                fail(&format!("Cannot write {}", genome_path.display()), err);
+            }
 
-    for (species_index, species) in population.species.iter().enumerate() {
-        for (organism_index, organism) in species.organisms.iter().enumerate() {
-            organism_indices.push((species_index, organism_index));
-            organisms.push(organism.clone());
+            // Drawing the network needs graphviz, which is not required for evolution
+            if let Err(err) = best.genome.compile_to_png(experiment.node_names(),
+                                                          &output_dir.join("dot").join(format!("{}.dot", generation)),
+                                                          &output_dir.join(format!("{}-{}.png", generation, best.fitness))) {
+                eprintln!("Cannot draw network: {}", err);
+            }
         }
-    }
 
-    let (results_send, results_recv): (Sender<(usize, usize, f64)>, Receiver<(usize, usize, f64)>) = channel();
-    let shared_organism_indices = Arc::new(organism_indices);
-    let shared_organisms = Arc::new(organisms); 
-    let mut threads = vec![];
+        let best_fitness = best.map(|organism| organism.fitness).unwrap_or(0.0);
 
-    for k in 0..num_threads {
-        let thread_organism_indices = shared_organism_indices.clone();
-        let thread_organisms = shared_organisms.clone();
-        let thread_results = results_send.clone();
-        let thread_experiment = experiment.clone();
+        if options.target_fitness.map(|target| best_fitness >= target).unwrap_or(false) {
+            println!("Reached target fitness {} in generation {}", best_fitness, generation);
+            break;
+        }
+
+        if generation + 1 >= options.max_generations {
+            break;
+        }
 
-        threads.push(thread::spawn(move || {
-            let a = num_tasks_per_thread*k;
-            let b = num_tasks_per_thread*(k+1);
-            let local_organism_indices = &thread_organism_indices[a..b];
-            let local_organisms = &thread_organisms[a..b];
+        if options.verbose {
+            println!("Generation {}", generation + 1);
+        }
 
-            for (&(species_index, organism_index), ref organism) in local_organism_indices.iter().zip(local_organisms) {
-                let mut network = organism.network.clone();
-                let fitness = thread_experiment.evaluate(&mut network, &thread_organisms[..]);
+        let stats = population.epoch(&mut rng);
+        if !stats.repairs.is_empty() {
+            eprintln!("Warning: repaired {} problems in the offspring of generation {}:", stats.repairs.len(), generation);
+            for problem in stats.repairs.iter() {
+                eprintln!("  {}", problem);
+            }
+        }
 
-                thread_results.send((species_index, organism_index, fitness)).unwrap();
+        for observer in observers.iter_mut() {
+            if let Err(err) = observer.observe(&stats) {
+                fail("Cannot write statistics", err);
             }
-        }));
-    }
+        }
 
-    // Receive changes and make the actual mutations in the population.
-    // Blocks until all organisms have been evaluated by the threads.
-    for _ in 0..num_population {
-        let (species_index, organism_index, fitness) = results_recv.recv().unwrap();
-        population.species[species_index].organisms[organism_index].fitness = fitness;
-    }
+        if options.verbose {
+            println!("");
+        }
 
-    for thread in threads.into_iter() {
-        thread.join().unwrap();
+        rng = generation_rng(seed, population.generation);
     }
 }
 
 fn main() {
-    let mut i = 0;
-    let mut rng = rand::thread_rng();
-
-    let num_population = 512;
-    //let mut experiment = exp::roadgame::RoadGameExperiment;
-    let mut experiment = exp::tictactoe::exp::TicTacToeExperiment::new();
-
-    let mut population = pop::Population::from_initial_genome(&mut rng,
-                                                              &experiment.population_settings(),
-                                                              &experiment.mutation_settings(),
-                                                              &experiment.compat_coefficients(),
-                                                              &experiment.initial_genome(),
-                                                              num_population);
-    evaluate(&mut experiment, &mut population);
+    let args: Vec<String> = env::args().collect();
+
+    let mut opts = Options::new();
+    opts.optopt("e", "experiment", &format!("experiment to run: {}", registry::names().join(", ")), "NAME");
+    opts.optopt("p", "population", "number of organisms (default 150)", "N");
+    opts.optopt("g", "generations", "maximal number of generations (default 1000)", "N");
+    opts.optopt("f", "target-fitness", "stop when an organism reaches this fitness", "FITNESS");
+    opts.optopt("t", "threads", "number of evaluation threads (default 1)", "N");
+    opts.optopt("s", "seed", "master seed of the run (default random, or the seed of the checkpoint)", "SEED");
+    opts.optopt("o", "output", "directory for networks, logs and checkpoints (default networks)", "DIR");
+    opts.optopt("c", "checkpoint-interval", "generations between checkpoints, 0 for none (default 10)", "N");
+    opts.optopt("r", "resume", "continue the run from a checkpoint", "FILE");
+    opts.optopt("", "config", "load settings from a JSON file", "FILE");
+    opts.optmulti("", "set", "change a single setting, e.g. mutation.new_node_prob=0.03", "FIELD=VALUE");
+    opts.optflag("v", "verbose", "print a summary of each generation");
+    opts.optflag("l", "list", "list the experiments");
+    opts.optflag("h", "help", "print this help");
+
+    let matches = match opts.parse(&args[1..]) {
+        Ok(matches) => matches,
+        Err(err) => fail("Invalid arguments", err)
+    };
 
-    loop {
-        i += 1;
+    if matches.opt_present("h") {
+        print!("{}", opts.usage(&format!("Usage: {} -e EXPERIMENT [options]", args[0])));
+        return;
+    }
 
-        if i > 5000 { 
-            break;
+    if matches.opt_present("l") {
+        for entry in registry::EXPERIMENTS.iter() {
+            println!("{:15} {}", entry.name, entry.description);
         }
+        return;
+    }
 
-        println!("Generation {}", i);
-        population.epoch(&mut rng);
-        println!("");
-
-        evaluate(&mut experiment, &mut population);
-
-        {
-            let mut best = population.best_organism().unwrap().clone();
+    let options = RunOptions {
+        population_size: parse_opt(&matches, "population").unwrap_or(150),
+        max_generations: parse_opt(&matches, "generations").unwrap_or(1000),
+        target_fitness: parse_opt(&matches, "target-fitness"),
+        num_threads: parse_opt(&matches, "threads").unwrap_or(1),
+        seed: parse_opt(&matches, "seed"),
+        output_dir: PathBuf::from(matches.opt_str("output").unwrap_or("networks".to_string())),
+        checkpoint_interval: parse_opt(&matches, "checkpoint-interval").unwrap_or(10),
+        resume: matches.opt_str("resume").map(PathBuf::from),
+        config_file: matches.opt_str("config").map(PathBuf::from),
+        settings: matches.opt_strs("set"),
+        verbose: matches.opt_present("verbose"),
+    };
 
-            println!("best: {}, best forkable: {}, random: {}, center: {}, bad: {}",
-                     exp::tictactoe::exp::score_network(&mut best.network, &mut exp::tictactoe::strats::BestStrategy { forkable: false }, 100),
-                     exp::tictactoe::exp::score_network(&mut best.network, &mut exp::tictactoe::strats::BestStrategy { forkable: true }, 100),
-                     exp::tictactoe::exp::score_network(&mut best.network, &mut exp::tictactoe::strats::RandomStrategy, 100),
-                     exp::tictactoe::exp::score_network(&mut best.network, &mut exp::tictactoe::strats::CenterStrategy, 100),
-                     exp::tictactoe::exp::score_network(&mut best.network, &mut exp::tictactoe::strats::BadStrategy, 100));
+    if options.population_size == 0 || options.num_threads == 0 {
+        fail("Invalid arguments", "population and threads must be positive");
+    }
+    if options.resume.is_some() && (options.config_file.is_some() || !options.settings.is_empty()) {
+        fail("Invalid arguments", "settings cannot be changed when resuming from a checkpoint");
+    }
+    if !options.output_dir.is_dir() {
+        fail("Output directory does not exist", options.output_dir.display());
+    }
 
-            best.genome.compile_to_png(experiment.node_names(),
-                                       Path::new(&format!("networks/dot/{}.dot", i)),
-                                       Path::new(&format!("networks/{}-{}.png", i, best.fitness))).unwrap();
-            best.genome.save(Path::new(&format!("networks/best/{}.json", i)));
-        }
+    match matches.opt_str("experiment") {
+        Some(name) => match registry::find(&name) {
+            Some(entry) => run(entry.new_experiment(), entry.default_config(), &options),
+            None => fail("Unknown experiment", name)
+        },
+        None => fail("Missing experiment", format!("choose one of {}", registry::names().join(", ")))
     }
 }
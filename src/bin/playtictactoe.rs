@@ -1,27 +1,38 @@
 extern crate getopts;
 extern crate neat;
+extern crate rand;
 
 use std::env;
+use std::process;
 use std::path::Path;
 
 use getopts::Options;
+use rand::{Rng, SeedableRng, StdRng};
 
 use neat::genes;
+use neat::exp::registry;
 use neat::exp::tictactoe;
 use neat::pop;
-use neat::exp::Experiment;
 
-fn play<S: tictactoe::game::Strategy>(network_strategy: &mut tictactoe::exp::NetworkStrategy,
+fn play<S: tictactoe::game::Strategy>(rng: &mut StdRng,
+                                      network_strategy: &mut tictactoe::exp::NetworkStrategy,
                                       network_player: tictactoe::game::Player,
                                       strategy: &mut S) -> Option<tictactoe::game::Player> {
     match network_player {
         tictactoe::game::Player::X =>
-            tictactoe::game::play(network_strategy, strategy, true),
+            tictactoe::game::play(rng, network_strategy, strategy, true),
         tictactoe::game::Player::O =>
-            tictactoe::game::play(strategy, network_strategy, true)
+            tictactoe::game::play(rng, strategy, network_strategy, true)
     }
 }
 
+/// Numbers of input and output nodes of the genome
+fn num_inputs_outputs(genome: &genes::Genome) -> (usize, usize) {
+    let count = |node_type| genome.nodes.iter().filter(|node| node.node_type == node_type).count();
+
+    (count(genes::NodeType::Input), count(genes::NodeType::Output))
+}
+
 fn main() {
     let args: Vec<String> = env::args().collect();
 
@@ -32,29 +43,50 @@ fn main() {
     }).unwrap();
     
     let network_path = Path::new(&args[2]);
-    let genome = genes::Genome::load(network_path);
-    let mut organism = pop::Organism::new(&genome);
-    let mut network_strategy = tictactoe::exp::NetworkStrategy { network: &mut organism.network };
+    let genome = match genes::Genome::load(network_path) {
+        Ok(genome) => genome,
+        Err(err) => {
+            eprintln!("Cannot load {}: {}", network_path.display(), err);
+            process::exit(1);
+        }
+    };
+
+    // The network must have been evolved for the tic-tac-toe experiment
+    let experiment = registry::find("tictactoe").expect("tictactoe is registered").new_experiment();
+    if num_inputs_outputs(&genome) != num_inputs_outputs(&experiment.initial_genome()) {
+        eprintln!("Cannot load {}: not a network for the tictactoe experiment", network_path.display());
+        process::exit(1);
+    }
+
+    let organism = match pop::Organism::new(&genome) {
+        Ok(organism) => organism,
+        Err(problems) => {
+            eprintln!("Cannot load {}: {}", network_path.display(), genes::Error::Integrity(problems));
+            process::exit(1);
+        }
+    };
+    let mut network_strategy = tictactoe::exp::NetworkStrategy::new(&organism.network);
     
     let n = args[4].parse::<usize>().unwrap();
+    let mut rng: StdRng = SeedableRng::from_seed(&[rand::thread_rng().gen::<usize>()][..]);
     let mut wins = 0;
     let mut draws = 0;
 
     for _ in 0..n {
         let winner = match args[3].trim() {
-            "best" => play(&mut network_strategy,
+            "best" => play(&mut rng, &mut network_strategy,
                            network_player,
                            &mut tictactoe::strats::BestStrategy { forkable: false }),
-            "forkable" => play(&mut network_strategy,
+            "forkable" => play(&mut rng, &mut network_strategy,
                                network_player,
                                &mut tictactoe::strats::BestStrategy { forkable: true }),
-            "center" => play(&mut network_strategy,
+            "center" => play(&mut rng, &mut network_strategy,
                              network_player,
                              &mut tictactoe::strats::CenterStrategy),
-            "random" => play(&mut network_strategy,
+            "random" => play(&mut rng, &mut network_strategy,
                              network_player,
                              &mut tictactoe::strats::RandomStrategy),
-            "input" => play(&mut network_strategy,
+            "input" => play(&mut rng, &mut network_strategy,
                             network_player,
                             &mut tictactoe::strats::InputStrategy),
             _ => {
@@ -0,0 +1,356 @@
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io;
+use std::path::Path;
+
+use rustc_serialize::Decodable;
+use rustc_serialize::json::{self, Json};
+
+use genes;
+use mutation;
+use parse;
+use pop;
+use validation;
+
+/// Reasons why settings cannot be loaded or changed
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Parse(String),
+    UnknownSetting(String),
+    NotAValue(String), // The field is a group of settings
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref err) => write!(f, "I/O error: {}", err),
+            Error::Parse(ref msg) => write!(f, "Invalid settings: {}", msg),
+            Error::UnknownSetting(ref field) => write!(f, "Unknown setting {}", field),
+            Error::NotAValue(ref field) => write!(f, "Setting {} is a group of settings, not a value", field),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Io(_) => "I/O error",
+            Error::Parse(_) => "invalid settings",
+            Error::UnknownSetting(_) => "unknown setting",
+            Error::NotAValue(_) => "setting is a group",
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl From<json::DecoderError> for Error {
+    fn from(err: json::DecoderError) -> Error {
+        Error::Parse(err.to_string())
+    }
+}
+
+impl From<json::EncoderError> for Error {
+    fn from(err: json::EncoderError) -> Error {
+        Error::Parse(err.to_string())
+    }
+}
+
+/// The settings of a run. Experiments give the defaults, which can be changed by a config file
+/// and by single values given on the command line.
+#[derive(Clone, Copy, RustcEncodable, RustcDecodable)]
+pub struct Config {
+    pub population: pop::Settings,
+    pub mutation: mutation::Settings,
+    pub compat: genes::CompatCoefficients,
+}
+
+impl Config {
+    /// Checks the settings for a population of `population_size` organisms
+    pub fn validate(&self, population_size: usize) -> Vec<validation::InvalidSetting> {
+        pop::validate_settings(&self.population, &self.mutation, &self.compat, population_size)
+    }
+
+    /// Changes the fields given in a JSON object of the form written by `save`.
+    /// Fields that are not given keep their value.
+    pub fn merge_json(&mut self, changes: &Json) -> Result<(), Error> {
+        let mut config_json = try!(parse::json(&try!(json::encode(self))).map_err(Error::Parse));
+        try!(merge(&mut config_json, changes, ""));
+
+        *self = try!(Decodable::decode(&mut json::Decoder::new(config_json)));
+
+        Ok(())
+    }
+
+    /// Changes the fields given in a config file
+    pub fn merge_file(&mut self, path: &Path) -> Result<(), Error> {
+        let mut f = try!(File::open(path));
+        let mut s = String::new();
+        try!(f.read_to_string(&mut s));
+
+        self.merge_json(&try!(parse::json(&s).map_err(Error::Parse)))
+    }
+
+    /// Changes a single field given as e.g. `mutation.new_node_prob=0.03`.
+    /// A value that is not JSON is taken as a string, e.g. `mutation.weight_distribution=Gaussian`.
+    pub fn set(&mut self, assignment: &str) -> Result<(), Error> {
+        let mut parts = assignment.splitn(2, '=');
+        let field = parts.next().unwrap();
+        let value = match parts.next() {
+            Some(value) => parse::json(value).unwrap_or_else(|_| Json::String(value.to_string())),
+            None => return Err(Error::Parse(format!("expected FIELD=VALUE, got {}", assignment)))
+        };
+
+        // Nest the value into objects along the path of the field
+        let changes = field.rsplit('.').fold(value, |json, name| {
+            let mut object = BTreeMap::new();
+            object.insert(name.to_string(), json);
+            Json::Object(object)
+        });
+
+        self.merge_json(&changes)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let mut f = try!(File::create(path));
+        try!(write!(f, "{}\n", json::as_pretty_json(self)));
+
+        Ok(())
+    }
+}
+
+/// Copies the values of `changes` into `json`, which must already have all the fields
+fn merge(json: &mut Json, changes: &Json, path: &str) -> Result<(), Error> {
+    match (json, changes) {
+        (&mut Json::Object(ref mut object), &Json::Object(ref changes)) => {
+            for (name, change) in changes.iter() {
+                let field_path = if path.is_empty() { name.clone() } else { format!("{}.{}", path, name) };
+
+                match object.get_mut(name) {
+                    Some(value) => try!(merge(value, change, &field_path)),
+                    None => return Err(Error::UnknownSetting(field_path))
+                }
+            }
+
+            Ok(())
+        },
+        (&mut Json::Object(_), _) =>
+            Err(Error::NotAValue(path.to_string())),
+        (json, change) => {
+            *json = change.clone();
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustc_serialize::json::Json;
+
+    use genes;
+    use mutation;
+    use pop;
+
+    use super::*;
+
+    fn config() -> Config {
+        Config {
+            population: pop::STANDARD_SETTINGS,
+            mutation: mutation::STANDARD_SETTINGS,
+            compat: genes::STANDARD_COMPAT_COEFFICIENTS,
+        }
+    }
+
+    #[test]
+    fn merge_json_changes_only_given_fields() {
+        let mut config = config();
+        let changes = Json::from_str(r#"{"mutation": {"new_link_prob": 0.25}, "compat": {"excess": 2.0}}"#).unwrap();
+        config.merge_json(&changes).unwrap();
+
+        assert_eq!(config.mutation.new_link_prob, 0.25);
+        assert_eq!(config.compat.excess, 2.0);
+        assert_eq!(config.mutation.new_node_prob, mutation::STANDARD_SETTINGS.new_node_prob);
+        assert_eq!(config.compat.disjoint, genes::STANDARD_COMPAT_COEFFICIENTS.disjoint);
+        assert_eq!(config.population.survival_threshold, pop::STANDARD_SETTINGS.survival_threshold);
+    }
+
+    #[test]
+    fn merge_json_rejects_unknown_settings_and_groups() {
+        let mut config = config();
+
+        // The known change before the unknown setting is not applied either
+        let unknown = Json::from_str(r#"{"compat": {"excess": 2.0}, "mutation": {"no_such_prob": 0.5}}"#).unwrap();
+        match config.merge_json(&unknown) {
+            Err(Error::UnknownSetting(field)) => assert_eq!(field, "mutation.no_such_prob"),
+            result => panic!("Unknown setting was accepted: {:?}", result)
+        }
+        assert_eq!(config.compat.excess, genes::STANDARD_COMPAT_COEFFICIENTS.excess);
+
+        let group = Json::from_str(r#"{"mutation": 0.5}"#).unwrap();
+        match config.merge_json(&group) {
+            Err(Error::NotAValue(field)) => assert_eq!(field, "mutation"),
+            result => panic!("Group was replaced by a value: {:?}", result)
+        }
+    }
+
+    #[test]
+    fn set_changes_nested_field() {
+        let mut config = config();
+
+        config.set("mutation.new_node_prob=0.3").unwrap();
+        assert_eq!(config.mutation.new_node_prob, 0.3);
+
+        config.set("population.dropoff_age=null").unwrap();
+        assert_eq!(config.population.dropoff_age, None);
+
+        config.set("population.dropoff_age=20").unwrap();
+        assert_eq!(config.population.dropoff_age, Some(20));
+    }
+
+    #[test]
+    fn set_takes_other_values_as_strings() {
+        let mut config = config();
+
+        config.set("mutation.weight_distribution=Gaussian").unwrap();
+        assert_eq!(config.mutation.weight_distribution, mutation::WeightDistribution::Gaussian);
+
+        config.set("mutation.weight_distribution=\"Cauchy\"").unwrap();
+        assert_eq!(config.mutation.weight_distribution, mutation::WeightDistribution::Cauchy);
+
+        match config.set("mutation.weight_distribution=Lognormal") {
+            Err(Error::Parse(_)) => (),
+            result => panic!("Unknown distribution was accepted: {:?}", result)
+        }
+    }
+
+    #[test]
+    fn set_rejects_malformed_assignments() {
+        let mut config = config();
+
+        for assignment in ["mutation.new_node_prob", "mutation.new_node_prob=", "mutation.new_node_prob=0.3.1"].iter() {
+            match config.set(assignment) {
+                Err(Error::Parse(_)) => (),
+                result => panic!("{} was accepted: {:?}", assignment, result)
+            }
+        }
+
+        // The value becomes an object, which does not decode as a probability
+        match config.set("mutation.new_node_prob.value=0.3") {
+            Err(Error::Parse(_)) => (),
+            result => panic!("Setting of a value was accepted: {:?}", result)
+        }
+        assert_eq!(config.mutation.new_node_prob, mutation::STANDARD_SETTINGS.new_node_prob);
+    }
+
+    /// Checks that the standard settings with the given changes are rejected because of `field` only
+    fn assert_rejected(assignments: &[&str], population_size: usize, field: &str) {
+        let mut config = config();
+        for assignment in assignments.iter() {
+            config.set(assignment).unwrap();
+        }
+
+        let fields = config.validate(population_size).into_iter().map(|setting| setting.field).collect::<Vec<_>>();
+        assert_eq!(fields, vec![field.to_string()], "{:?}", assignments);
+    }
+
+    #[test]
+    fn standard_settings_are_valid() {
+        assert!(config().validate(150).is_empty());
+    }
+
+    #[test]
+    fn validate_rejects_survival_threshold() {
+        assert_rejected(&["population.survival_threshold=1.5"], 150, "population.survival_threshold");
+        assert_rejected(&["population.survival_threshold=-0.1"], 150, "population.survival_threshold");
+    }
+
+    #[test]
+    fn validate_rejects_compat_threshold() {
+        assert_rejected(&["population.compat_threshold=0"], 150, "population.compat_threshold");
+        assert_rejected(&["population.compat_threshold=0.1", "population.target_num_species=10"], 150,
+                        "population.compat_threshold");
+    }
+
+    #[test]
+    fn validate_rejects_dropoff_age() {
+        assert_rejected(&["population.dropoff_age=0"], 150, "population.dropoff_age");
+    }
+
+    #[test]
+    fn validate_rejects_target_num_species() {
+        assert_rejected(&["population.target_num_species=0"], 150, "population.target_num_species");
+        assert_rejected(&["population.target_num_species=20"], 10, "population.target_num_species");
+    }
+
+    #[test]
+    fn validate_rejects_population_size() {
+        assert_rejected(&["population.target_num_species=null"], 0, "population_size");
+    }
+
+    #[test]
+    fn validate_rejects_phased_search() {
+        let phased_search = r#"population.phased_search={"complexity_threshold": 10.0, "stall_generations": 5,
+                                                          "delete_link_prob": 0.1, "delete_node_prob": 0.1}"#;
+
+        assert_rejected(&[phased_search, "population.phased_search.complexity_threshold=0"], 150,
+                        "population.phased_search.complexity_threshold");
+        assert_rejected(&[phased_search, "population.phased_search.stall_generations=0"], 150,
+                        "population.phased_search.stall_generations");
+        assert_rejected(&[phased_search, "population.phased_search.delete_link_prob=2"], 150,
+                        "population.phased_search.delete_link_prob");
+        assert_rejected(&[phased_search, "population.phased_search.delete_node_prob=-1"], 150,
+                        "population.phased_search.delete_node_prob");
+    }
+
+    #[test]
+    fn validate_rejects_mutation_probabilities() {
+        for field in ["new_node_prob", "new_link_prob", "delete_link_prob", "delete_node_prob",
+                      "change_link_weights_prob", "uniform_perturbation_prob", "weight_mutation_rate",
+                      "recurrent_link_prob", "self_link_prob", "toggle_enable_prob", "change_activation_prob",
+                      "mutate_only_prob", "mutate_after_mating_prob", "no_crossover_prob", "interspecies_mating_prob",
+                      "multipoint_avg_prob", "singlepoint_prob", "disable_prob"].iter() {
+            assert_rejected(&[&format!("mutation.{}=1.5", field)], 150, &format!("mutation.{}", field));
+        }
+    }
+
+    #[test]
+    fn validate_rejects_crossover_probabilities() {
+        assert_rejected(&["mutation.multipoint_prob=0", "mutation.multipoint_avg_prob=0", "mutation.singlepoint_prob=0"],
+                        150, "mutation.multipoint_prob");
+    }
+
+    #[test]
+    fn validate_rejects_change_link_weights_power() {
+        assert_rejected(&["mutation.change_link_weights_power=0"], 150, "mutation.change_link_weights_power");
+    }
+
+    #[test]
+    fn validate_rejects_weight_range() {
+        assert_rejected(&["mutation.min_weight=8", "mutation.max_weight=-8"], 150, "mutation.min_weight");
+        assert_rejected(&["mutation.min_weight=1", "mutation.max_weight=1"], 150, "mutation.min_weight");
+    }
+
+    #[test]
+    fn validate_rejects_empty_activation_functions() {
+        let none = r#"mutation.activation_functions={"tanh": false, "steepened_sigmoid": false, "relu": false,
+                                                      "identity": false, "step": false, "gaussian": false,
+                                                      "sine": false, "abs": false}"#;
+
+        assert_rejected(&[none], 150, "mutation.activation_functions");
+    }
+
+    #[test]
+    fn validate_rejects_compat_coefficients() {
+        for field in ["disjoint", "excess", "weight_diff"].iter() {
+            assert_rejected(&[&format!("compat.{}=-1", field)], 150, &format!("compat.{}", field));
+        }
+    }
+}
@@ -0,0 +1,304 @@
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::channel;
+use std::thread;
+use rand::{self, SeedableRng, StdRng};
+
+use exp;
+use pop;
+
+/// Assigns fitness to the organisms of a population by running an experiment on them
+pub struct Evaluator {
+    num_threads: usize,
+}
+
+/// An organism whose evaluation panicked or gave an invalid fitness. It is assigned zero fitness.
+#[derive(Clone, Debug)]
+pub struct Failure {
+    pub species_id: usize,
+    pub organism_index: usize,
+    pub message: String,
+}
+
+impl Evaluator {
+    /// With one thread, organisms are evaluated in the calling thread
+    pub fn new(num_threads: usize) -> Evaluator {
+        assert!(num_threads > 0, "Need at least one thread for evaluation");
+
+        Evaluator {
+            num_threads: num_threads,
+        }
+    }
+
+    pub fn num_threads(&self) -> usize {
+        self.num_threads
+    }
+
+    /// Evaluates every organism of the population, then lets the experiment look at the results.
+    /// Failing organisms do not stop the evaluation of the others.
+    /// Each organism gets a generator seeded from `rng`, so the fitness values are the same
+    /// for any number of threads.
+    pub fn evaluate<E, R>(&self, rng: &mut R, experiment: &mut E, population: &mut pop::Population) -> Vec<Failure>
+        where E: exp::Experiment + Send + Sync + 'static, R: rand::Rng {
+        // Flatten the population so that organisms can be handed out by index
+        let mut organism_indices = vec![];
+        let mut organisms = vec![];
+
+        for (species_index, species) in population.species.iter().enumerate() {
+            for (organism_index, organism) in species.organisms.iter().enumerate() {
+                organism_indices.push((species_index, organism_index));
+                organisms.push(organism.clone());
+            }
+        }
+
+        let seeds = organisms.iter().map(|_| new_seed(rng)).collect::<Vec<_>>();
+
+        let results = if self.num_threads == 1 {
+            organisms.iter()
+                     .zip(seeds.iter())
+                     .map(|(organism, seed)| evaluate_organism(experiment, seed, organism, &organisms))
+                     .collect()
+        } else {
+            evaluate_multi_threaded(Arc::new(experiment.clone()),
+                                    Arc::new(seeds),
+                                    Arc::new(organisms),
+                                    self.num_threads)
+        };
+
+        let mut failures = vec![];
+
+        for (&(species_index, organism_index), result) in organism_indices.iter().zip(results.into_iter()) {
+            let species = &mut population.species[species_index];
+
+            species.organisms[organism_index].fitness = match result {
+                Ok(fitness) => fitness,
+                Err(message) => {
+                    failures.push(Failure {
+                        species_id: species.id,
+                        organism_index: organism_index,
+                        message: message,
+                    });
+
+                    0.0
+                }
+            };
+        }
+
+        experiment.post_evaluation(population);
+
+        failures
+    }
+}
+
+fn panic_message(payload: Box<Any + Send>) -> String {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => match payload.downcast_ref::<String>() {
+            Some(message) => message.clone(),
+            None => "Evaluation panicked".to_string()
+        }
+    }
+}
+
+/// Seed for an independent stream of random numbers
+fn new_seed<R: rand::Rng>(rng: &mut R) -> Vec<usize> {
+    (0..4).map(|_| rng.gen::<usize>()).collect()
+}
+
+fn evaluate_organism<E: exp::Experiment>(experiment: &E,
+                                         seed: &[usize],
+                                         organism: &pop::Organism,
+                                         organisms: &[pop::Organism]) -> Result<f64, String> {
+    let mut rng: StdRng = SeedableRng::from_seed(seed);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| experiment.evaluate(&mut rng, &organism.network, organisms)));
+
+    match result {
+        Ok(fitness) =>
+            if fitness >= 0.0 && fitness.is_finite() {
+                Ok(fitness)
+            } else {
+                Err(format!("Invalid fitness {}", fitness))
+            },
+        Err(payload) => Err(panic_message(payload))
+    }
+}
+
+/// Threads take the next unevaluated organism from a shared counter until none are left,
+/// so that threads which get fast organisms do not sit idle
+fn evaluate_multi_threaded<E>(experiment: Arc<E>,
+                              seeds: Arc<Vec<Vec<usize>>>,
+                              organisms: Arc<Vec<pop::Organism>>,
+                              num_threads: usize) -> Vec<Result<f64, String>>
+    where E: exp::Experiment + Send + Sync + 'static {
+    let num_organisms = organisms.len();
+    let next_index = Arc::new(AtomicUsize::new(0));
+
+    let (results_send, results_recv) = channel();
+    let mut threads = vec![];
+
+    for _ in 0..num_threads {
+        let thread_experiment = experiment.clone();
+        let thread_seeds = seeds.clone();
+        let thread_organisms = organisms.clone();
+        let thread_next_index = next_index.clone();
+        let thread_results = results_send.clone();
+
+        threads.push(thread::spawn(move || {
+            loop {
+                let index = thread_next_index.fetch_add(1, Ordering::SeqCst);
+
+                if index >= thread_organisms.len() {
+                    break;
+                }
+
+                let result = evaluate_organism(&*thread_experiment,
+                                               &thread_seeds[index],
+                                               &thread_organisms[index],
+                                               &thread_organisms[..]);
+                thread_results.send((index, result)).unwrap();
+            }
+        }));
+    }
+
+    // Only the threads may hold senders, so that the channel closes once they are all done
+    drop(results_send);
+
+    // Results arrive in any order, put them back in the order of the organisms
+    let mut results = (0..num_organisms).map(|_| None).collect::<Vec<Option<Result<f64, String>>>>();
+
+    for (index, result) in results_recv.iter() {
+        results[index] = Some(result);
+    }
+
+    for thread in threads.into_iter() {
+        let _ = thread.join();
+    }
+
+    // A thread that died outside of an evaluation leaves organisms without a result
+    results.into_iter()
+           .map(|result| result.unwrap_or_else(|| Err("Evaluation thread stopped".to_string())))
+           .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::f64;
+    use rand::{Rng, SeedableRng, StdRng};
+
+    use exp;
+    use genes;
+    use mutation;
+    use nn;
+    use pop;
+    use super::*;
+
+    /// What the evaluation of an organism does, decided by the organism's own generator
+    #[derive(Clone, Copy, PartialEq)]
+    enum Outcome {
+        Fitness, // Always a valid fitness in [1,2)
+        Panic, // Panics for about half of the organisms
+        InvalidFitness, // NaN, infinite or negative fitness for about half of the organisms
+    }
+
+    #[derive(Clone)]
+    struct TestExperiment {
+        outcome: Outcome,
+    }
+
+    impl exp::Experiment for TestExperiment {
+        fn population_settings(&self) -> pop::Settings { pop::STANDARD_SETTINGS }
+        fn mutation_settings(&self) -> mutation::Settings { mutation::STANDARD_SETTINGS }
+        fn compat_coefficients(&self) -> genes::CompatCoefficients { genes::STANDARD_COMPAT_COEFFICIENTS }
+
+        fn initial_genome(&self) -> genes::Genome { genes::Genome::initial_genome(2, 1, 2, true) }
+        fn node_names(&self) -> HashMap<genes::NodeId, String> { HashMap::new() }
+
+        fn evaluate(&self, rng: &mut StdRng, _: &nn::Network, _: &[pop::Organism]) -> f64 {
+            let x = rng.gen::<f64>();
+
+            match self.outcome {
+                Outcome::Panic if x < 0.5 => panic!("Organism failed"),
+                Outcome::InvalidFitness if x < 0.2 => f64::NAN,
+                Outcome::InvalidFitness if x < 0.35 => f64::INFINITY,
+                Outcome::InvalidFitness if x < 0.5 => -x,
+                _ => 1.0 + x
+            }
+        }
+
+        fn evaluate_to_string(&self, _: &nn::Network) -> String { String::new() }
+    }
+
+    fn population(num_organisms: usize) -> pop::Population {
+        let mut rng: StdRng = SeedableRng::from_seed(&[5usize][..]);
+        let settings = pop::Settings {
+            target_num_species: None,
+            .. pop::STANDARD_SETTINGS
+        };
+
+        pop::Population::from_initial_genome(&mut rng, &settings, &mutation::STANDARD_SETTINGS,
+                                             &genes::STANDARD_COMPAT_COEFFICIENTS,
+                                             &genes::Genome::initial_genome(2, 1, 2, true),
+                                             num_organisms).unwrap()
+    }
+
+    /// Evaluates a new population with a fixed seed, returns the fitness of every organism and the failures
+    fn evaluate(outcome: Outcome, num_organisms: usize, num_threads: usize) -> (Vec<f64>, Vec<Failure>) {
+        let mut population = population(num_organisms);
+        let mut rng: StdRng = SeedableRng::from_seed(&[7usize][..]);
+        let mut experiment = TestExperiment { outcome: outcome };
+
+        let failures = Evaluator::new(num_threads).evaluate(&mut rng, &mut experiment, &mut population);
+        let fitnesses = population.species.iter()
+                                  .flat_map(|species| species.organisms.iter())
+                                  .map(|organism| organism.fitness)
+                                  .collect();
+
+        (fitnesses, failures)
+    }
+
+    #[test]
+    fn every_organism_is_evaluated_with_uneven_threads() {
+        for &num_threads in [1, 3, 4, 7].iter() {
+            let (fitnesses, failures) = evaluate(Outcome::Fitness, 50, num_threads);
+
+            assert_eq!(fitnesses.len(), 50);
+            assert!(fitnesses.iter().all(|&fitness| fitness >= 1.0), "{} threads left organisms unevaluated", num_threads);
+            assert!(failures.is_empty());
+        }
+    }
+
+    #[test]
+    fn panicking_organisms_fail_without_stopping_the_others() {
+        for &num_threads in [1, 4].iter() {
+            let (fitnesses, failures) = evaluate(Outcome::Panic, 50, num_threads);
+
+            assert!(!failures.is_empty());
+            assert!(failures.iter().all(|failure| failure.message == "Organism failed"));
+            assert_eq!(fitnesses.iter().filter(|&&fitness| fitness == 0.0).count(), failures.len());
+            assert_eq!(fitnesses.iter().filter(|&&fitness| fitness >= 1.0).count(), 50 - failures.len());
+        }
+    }
+
+    #[test]
+    fn invalid_fitness_is_a_failure() {
+        let (fitnesses, failures) = evaluate(Outcome::InvalidFitness, 50, 4);
+
+        for invalid in ["NaN", "inf", "-0."].iter() {
+            assert!(failures.iter().any(|failure| failure.message.starts_with(&format!("Invalid fitness {}", invalid))),
+                    "No failure for fitness {}", invalid);
+        }
+        assert_eq!(fitnesses.iter().filter(|&&fitness| fitness == 0.0).count(), failures.len());
+        assert!(fitnesses.iter().all(|&fitness| fitness == 0.0 || fitness >= 1.0));
+    }
+
+    #[test]
+    fn fitness_does_not_depend_on_number_of_threads() {
+        let (single, _) = evaluate(Outcome::Fitness, 50, 1);
+        let (multi, _) = evaluate(Outcome::Fitness, 50, 6);
+
+        assert_eq!(single, multi);
+    }
+}
@@ -0,0 +1,420 @@
+use std::collections::HashMap;
+use rand::StdRng;
+
+use genes;
+use nn;
+use exp;
+use pop;
+use mutation;
+
+/// Balancing two poles of different lengths on a cart, as used by Gruau et al. and in the NEAT paper.
+/// Without velocities, the network has to estimate them itself, which requires recurrent links.
+#[derive(Clone)]
+pub struct DoublePoleExperiment {
+    pub velocities: bool, // Markovian variant if true
+    pub max_steps: usize, // The poles count as balanced after this many steps
+}
+
+// Cart state: x, x_dot, theta1, theta1_dot, theta2, theta2_dot
+type PoleState = [f64; 6];
+
+const X_BOUND: f64 = 2.4;
+const THIRTY_SIX_DEGREES: f64 = 0.628329;
+
+// Gruau's fitness looks at the first 1000 steps, measuring oscillation in the last 100 of them
+const DAMPING_STEPS: usize = 1000;
+const DAMPING_WINDOW: usize = 100;
+
+// The generalization test starts from 625 different states and runs each for 1000 steps
+const GENERALIZATION_STEPS: usize = 1000;
+const GENERALIZATION_POINTS: &'static [f64] = &[0.05, 0.25, 0.5, 0.75, 0.95];
+
+// Same as the single pole dynamics in `pole`, extended to two poles with friction
+// as described by Wieland, 1991
+fn derivatives(force: f64, state: &PoleState) -> PoleState {
+    const GRAVITY: f64 = -9.8;
+    const MASS_CART: f64 = 1.0;
+    const MASS_POLE_1: f64 = 0.1;
+    const MASS_POLE_2: f64 = 0.01;
+    const HALF_LENGTH_1: f64 = 0.5;
+    const HALF_LENGTH_2: f64 = 0.05;
+    const MU_P: f64 = 0.000002; // Friction of the poles' hinges
+
+    let pole = |theta: f64, theta_dot: f64, mass: f64, half_length: f64| {
+        let cos_theta = theta.cos();
+        let g_sin_theta = GRAVITY * theta.sin();
+        let mass_length = mass * half_length;
+        let friction = MU_P * theta_dot / mass_length;
+
+        // Effective force and mass of the pole acting on the cart
+        let force = mass_length * theta_dot.powi(2) * theta.sin() +
+                    0.75 * mass * cos_theta * (friction + g_sin_theta);
+        let mass = mass * (1.0 - 0.75 * cos_theta.powi(2));
+
+        (force, mass, cos_theta, g_sin_theta, friction)
+    };
+
+    let (force_1, mass_1, cos_theta_1, g_sin_theta_1, friction_1) =
+        pole(state[2], state[3], MASS_POLE_1, HALF_LENGTH_1);
+    let (force_2, mass_2, cos_theta_2, g_sin_theta_2, friction_2) =
+        pole(state[4], state[5], MASS_POLE_2, HALF_LENGTH_2);
+
+    let x_acc = (force + force_1 + force_2) / (mass_1 + mass_2 + MASS_CART);
+
+    [state[1],
+     x_acc,
+     state[3],
+     -0.75 * (x_acc * cos_theta_1 + g_sin_theta_1 + friction_1) / HALF_LENGTH_1,
+     state[5],
+     -0.75 * (x_acc * cos_theta_2 + g_sin_theta_2 + friction_2) / HALF_LENGTH_2]
+}
+
+// Fourth-order Runge-Kutta integration
+fn runge_kutta_step(force: f64, state: &PoleState, tau: f64) -> PoleState {
+    let shift = |state: &PoleState, derivs: &PoleState, h: f64| {
+        let mut shifted = *state;
+        for i in 0..6 {
+            shifted[i] += h * derivs[i];
+        }
+        shifted
+    };
+
+    let k1 = derivatives(force, state);
+    let k2 = derivatives(force, &shift(state, &k1, tau / 2.0));
+    let k3 = derivatives(force, &shift(state, &k2, tau / 2.0));
+    let k4 = derivatives(force, &shift(state, &k3, tau));
+
+    let mut new_state = *state;
+    for i in 0..6 {
+        new_state[i] += tau / 6.0 * (k1[i] + 2.0 * k2[i] + 2.0 * k3[i] + k4[i]);
+    }
+    new_state
+}
+
+/// `output` is in (-1,1) and scales the force applied to the cart
+fn double_pole_step(state: &mut PoleState, output: f64) {
+    const FORCE_MAG: f64 = 10.0;
+    const TAU: f64 = 0.01; // Two integration steps between each action
+
+    let force = output.max(-1.0).min(1.0) * FORCE_MAG;
+
+    for _ in 0..2 {
+        *state = runge_kutta_step(force, state, TAU);
+    }
+}
+
+fn initial_state() -> PoleState {
+    // The long pole starts at about 4 degrees
+    [0.0, 0.0, 0.07, 0.0, 0.0, 0.0]
+}
+
+fn has_failed(state: &PoleState) -> bool {
+    state[0].abs() > X_BOUND ||
+    state[2].abs() > THIRTY_SIX_DEGREES ||
+    state[4].abs() > THIRTY_SIX_DEGREES
+}
+
+fn state_to_string(state: &PoleState) -> String {
+    format!("x: {:7.3}, x_dot: {:7.3}, theta1: {:7.3}, theta1_dot: {:7.3}, theta2: {:7.3}, theta2_dot: {:7.3}",
+            state[0], state[1], state[2], state[3], state[4], state[5])
+}
+
+impl DoublePoleExperiment {
+    pub fn new(velocities: bool) -> DoublePoleExperiment {
+        DoublePoleExperiment {
+            velocities: velocities,
+            max_steps: 100000,
+        }
+    }
+
+    fn network_input(&self, state: &PoleState, network: &nn::Network, net_state: &mut nn::State) -> f64 {
+        // Inputs are scaled to be roughly in [-1,1]
+        let input = if self.velocities {
+            vec![(0, state[0] / 4.8),
+                 (1, state[1] / 2.0),
+                 (2, state[2] / 0.52),
+                 (3, state[3] / 2.0),
+                 (4, state[4] / 0.52),
+                 (5, state[5] / 2.0)]
+        } else {
+            vec![(0, state[0] / 4.8),
+                 (1, state[2] / 0.52),
+                 (2, state[4] / 0.52)]
+        };
+        network.set_input(net_state, &input);
+
+        if network.is_feedforward() {
+            network.activate_feedforward(net_state);
+        } else {
+            for _ in 1..10 {
+                network.activate(net_state);
+            }
+        }
+
+        network.get_output(net_state)[0].1
+    }
+
+    /// Balances the poles starting in `state` for at most `max_steps`, returning the number of steps until failure.
+    /// `f` is called with the state after every step.
+    fn run<F: FnMut(&PoleState, f64)>(&self, state: &mut PoleState, max_steps: usize,
+                                      network: &nn::Network, net_state: &mut nn::State,
+                                      mut f: F) -> usize {
+        network.flush(net_state);
+
+        for steps in 0..max_steps {
+            if has_failed(state) {
+                return steps;
+            }
+
+            let output = self.network_input(state, network, net_state);
+            f(state, output);
+
+            double_pole_step(state, output);
+        }
+
+        max_steps
+    }
+
+    /// Fitness as defined by Gruau et al., 1996, punishing networks that balance the poles by wildly
+    /// moving the cart back and forth. Without velocities, that would be the easiest way to balance.
+    pub fn damping_fitness(&self, network: &nn::Network) -> f64 {
+        let mut net_state = network.new_state();
+        let mut state = initial_state();
+        let mut jiggle = Vec::with_capacity(DAMPING_STEPS);
+
+        let steps = self.run(&mut state, DAMPING_STEPS, network, &mut net_state, |state, _| {
+            jiggle.push(state[0].abs() + state[1].abs() + state[2].abs() + state[3].abs());
+        });
+
+        let f1 = steps as f64 / DAMPING_STEPS as f64;
+        let f2 = if steps < DAMPING_WINDOW {
+            0.0
+        } else {
+            0.75 / jiggle[steps-DAMPING_WINDOW..steps].iter().fold(0.0, |x, y| x + y)
+        };
+
+        0.1 * f1 + 0.9 * f2
+    }
+
+    /// Number of steps for which the poles are balanced from the standard initial state
+    pub fn balanced_steps(&self, network: &nn::Network) -> usize {
+        let mut net_state = network.new_state();
+        let mut state = initial_state();
+
+        self.run(&mut state, self.max_steps, network, &mut net_state, |_, _| ())
+    }
+
+    /// Number of the 625 standard start states out of which the poles are balanced for 1000 steps.
+    /// Gruau et al. count a network that succeeds in at least 200 of them as a solution.
+    pub fn generalization_score(&self, network: &nn::Network) -> usize {
+        let mut net_state = network.new_state();
+        let mut num_balanced = 0;
+
+        for &x in GENERALIZATION_POINTS.iter() {
+            for &x_dot in GENERALIZATION_POINTS.iter() {
+                for &theta in GENERALIZATION_POINTS.iter() {
+                    for &theta_dot in GENERALIZATION_POINTS.iter() {
+                        let mut state = [x * 4.32 - 2.16,
+                                         x_dot * 2.70 - 1.35,
+                                         theta * 0.12566304 - 0.06283152,
+                                         theta_dot * 0.30019504 - 0.15009752,
+                                         0.0,
+                                         0.0];
+
+                        if self.run(&mut state, GENERALIZATION_STEPS, network, &mut net_state, |_, _| ()) ==
+                           GENERALIZATION_STEPS {
+                            num_balanced += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        num_balanced
+    }
+}
+
+impl exp::Experiment for DoublePoleExperiment {
+    fn population_settings(&self) -> pop::Settings {
+        pop::STANDARD_SETTINGS
+    }
+
+    fn mutation_settings(&self) -> mutation::Settings {
+        if self.velocities {
+            mutation::STANDARD_SETTINGS
+        } else {
+            mutation::Settings { recurrent_link_prob: 0.2, .. mutation::STANDARD_SETTINGS }
+        }
+    }
+
+    fn compat_coefficients(&self) -> genes::CompatCoefficients {
+        genes::STANDARD_COMPAT_COEFFICIENTS
+    }
+
+    fn initial_genome(&self) -> genes::Genome {
+        if self.velocities {
+            genes::Genome::initial_genome(6, 1, 6, true)
+        } else {
+            genes::Genome::initial_genome(3, 1, 3, true)
+        }
+    }
+
+    fn node_names(&self) -> HashMap<genes::NodeId, String> {
+        let mut map = HashMap::new();
+
+        let names = if self.velocities {
+            vec!["X", "X_DOT", "THETA1", "THETA1_DOT", "THETA2", "THETA2_DOT"]
+        } else {
+            vec!["X", "THETA1", "THETA2"]
+        };
+
+        for (i, name) in names.iter().enumerate() {
+            map.insert(i, name.to_string());
+        }
+
+        map.insert(names.len(), "B".to_string());
+        map.insert(names.len() + 1, "F".to_string());
+
+        map
+    }
+
+    fn evaluate(&self, _rng: &mut StdRng, network: &nn::Network, _organisms: &[pop::Organism]) -> f64 {
+        if self.velocities {
+            self.balanced_steps(network) as f64
+        } else {
+            self.damping_fitness(network)
+        }
+    }
+
+    /// Solved if the poles are balanced for `max_steps`. Without velocities, the network must also
+    /// generalize to 200 of the standard start states, as required by Gruau et al.
+    fn is_solved(&self, network: &nn::Network) -> bool {
+        self.balanced_steps(network) == self.max_steps &&
+            (self.velocities || self.generalization_score(network) >= 200)
+    }
+
+    fn evaluate_to_string(&self, network: &nn::Network) -> String {
+        // Only the beginning of the run is traced
+        const MAX_TRACE_STEPS: usize = 1000;
+
+        let mut net_state = network.new_state();
+        let mut state = initial_state();
+        let mut str = String::new();
+        let mut step = 0;
+
+        let steps = self.run(&mut state, self.max_steps, network, &mut net_state, |state, output| {
+            if step < MAX_TRACE_STEPS {
+                str.push_str(&format!("{:6}: {} -> {:.3}\n", step, state_to_string(state), output));
+            }
+            step += 1;
+        });
+
+        format!("Balanced for {} of {} steps\nDamping fitness: {}\nGeneralization: {} of 625\n",
+                steps, self.max_steps, self.damping_fitness(network), self.generalization_score(network)) +
+        &str
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use genes;
+    use genes::fixtures;
+    use nn;
+    use exp::Experiment;
+    use super::*;
+
+    /// A network of the variant whose output is the given input, or 0 if there is none
+    fn network(experiment: &DoublePoleExperiment, input_id: Option<genes::NodeId>) -> nn::Network {
+        let num_inputs = if experiment.velocities { 6 } else { 3 };
+        let mut genome = genes::Genome::initial_genome(num_inputs, 1, 0, false);
+        genome.set_output_activation(genes::ActivationFunction::Identity);
+
+        if let Some(input_id) = input_id {
+            genome.add_link(fixtures::link(input_id, num_inputs + 1, 0));
+        }
+
+        nn::Network::from_genome(&genome).unwrap()
+    }
+
+    fn assert_close(state: &PoleState, expected: &PoleState) {
+        for i in 0..6 {
+            assert!((state[i] - expected[i]).abs() < 1e-12, "{:?} != {:?}", state, expected);
+        }
+    }
+
+    #[test]
+    fn runge_kutta_step_matches_reference() {
+        // Computed with the equations of the NEAT reference implementation
+        assert_close(&runge_kutta_step(10.0, &initial_state(), 0.01),
+                     &[0.00048395833189532816, 0.09679344677009202, 0.06932716056109443,
+                       -0.13458811370541823, -0.007266799964103384, -1.455012609347313]);
+        assert_close(&runge_kutta_step(0.0, &initial_state(), 0.01),
+                     &[-2.4949280932231116e-06, -0.000499053992934439, 0.07005514794456898,
+                       0.011031037274709126, 3.746227683081945e-05, 0.007501894587706912]);
+    }
+
+    #[test]
+    fn fails_out_of_bounds() {
+        assert!(!has_failed(&initial_state()));
+        assert!(!has_failed(&[2.3, 5.0, 0.62, 5.0, -0.62, 5.0]));
+        assert!(has_failed(&[2.5, 0.0, 0.0, 0.0, 0.0, 0.0]));
+        assert!(has_failed(&[-2.5, 0.0, 0.0, 0.0, 0.0, 0.0]));
+        assert!(has_failed(&[0.0, 0.0, 0.63, 0.0, 0.0, 0.0]));
+        assert!(has_failed(&[0.0, 0.0, 0.0, 0.0, -0.63, 0.0]));
+    }
+
+    #[test]
+    fn poles_fall_without_force() {
+        let experiment = DoublePoleExperiment::new(true);
+
+        // Computed with the equations of the NEAT reference implementation
+        assert_eq!(experiment.balanced_steps(&network(&experiment, None)), 23);
+    }
+
+    #[test]
+    fn variants_differ_in_inputs() {
+        let markovian = DoublePoleExperiment::new(true);
+        let non_markovian = DoublePoleExperiment::new(false);
+        let num_inputs = |experiment: &DoublePoleExperiment| {
+            experiment.initial_genome().nodes.iter().filter(|node| node.node_type == genes::NodeType::Input).count()
+        };
+
+        assert_eq!(num_inputs(&markovian), 6);
+        assert_eq!(num_inputs(&non_markovian), 3);
+        assert_eq!(markovian.mutation_settings().recurrent_link_prob, mutation::STANDARD_SETTINGS.recurrent_link_prob);
+        assert_eq!(non_markovian.mutation_settings().recurrent_link_prob, 0.2);
+
+        // The second input is the cart velocity with velocities, the angle of the long pole without
+        let state = [0.0, 1.0, 0.13, 0.0, 0.0, 0.0];
+        let second_input = |experiment: &DoublePoleExperiment| {
+            let network = network(experiment, Some(1));
+            let mut net_state = network.new_state();
+            experiment.network_input(&state, &network, &mut net_state)
+        };
+
+        assert!((second_input(&markovian) - 0.5).abs() < 1e-12);
+        assert!((second_input(&non_markovian) - 0.25).abs() < 1e-12);
+    }
+
+    #[test]
+    fn variants_differ_in_fitness_and_solution() {
+        let markovian = DoublePoleExperiment { max_steps: 5, .. DoublePoleExperiment::new(true) };
+        let non_markovian = DoublePoleExperiment { max_steps: 5, .. DoublePoleExperiment::new(false) };
+        let mut rng: StdRng = rand::SeedableRng::from_seed(&[1usize][..]);
+
+        let markovian_net = network(&markovian, None);
+        assert_eq!(markovian.evaluate(&mut rng, &markovian_net, &[]), 5.0);
+        assert!(markovian.is_solved(&markovian_net));
+
+        // Without velocities, fitness is the damping fitness and a solution has to generalize.
+        // The poles fall after 23 steps, too early to measure oscillation.
+        let non_markovian_net = network(&non_markovian, None);
+        let fitness = non_markovian.evaluate(&mut rng, &non_markovian_net, &[]);
+        assert!((fitness - 0.1 * 23.0 / DAMPING_STEPS as f64).abs() < 1e-12, "{}", fitness);
+        assert_eq!(non_markovian.balanced_steps(&non_markovian_net), 5);
+        assert!(!non_markovian.is_solved(&non_markovian_net));
+
+        // Without force, only the 5 start states with the cart and the long pole at rest and upright stay balanced
+        assert_eq!(non_markovian.generalization_score(&non_markovian_net), 5);
+    }
+}
@@ -1,8 +1,16 @@
 pub mod roadgame;
 pub mod pole;
+pub mod doublepole;
 pub mod tictactoe;
+pub mod xor;
+pub mod registry;
 
 use std::collections::HashMap;
+use std::fmt;
+use rand;
+use rand::StdRng;
+use rustc_serialize::json::{DecoderError, Json};
+use eval;
 use genes;
 use nn;
 use pop;
@@ -16,8 +24,176 @@ pub trait Experiment : Clone {
     fn initial_genome(&self) -> genes::Genome;
     fn node_names(&self) -> HashMap<genes::NodeId, String>;
 
-    fn evaluate(&self, network: &mut nn::Network, organisms: &[pop::Organism]) -> f64;
+    /// Gives the fitness of a network. `rng` is the organism's own random number generator,
+    /// so that the result does not depend on the order in which organisms are evaluated.
+    fn evaluate(&self, rng: &mut StdRng, network: &nn::Network, organisms: &[pop::Organism]) -> f64;
+
+    /// Called after every organism of the population has been evaluated
+    fn post_evaluation(&mut self, _population: &pop::Population) {
+    }
+
+    fn evaluate_to_string(&self, network: &nn::Network) -> String;
+
+    /// Whether the network counts as a solution of the task. Used to measure how long evolution takes.
+    /// Tasks without a solution, such as games against evolving opponents, are never solved.
+    fn is_solved(&self, _network: &nn::Network) -> bool {
+        false
+    }
+
+    /// State that changes during evolution, such as opponents collected so far. Stored in checkpoints.
+    fn save_state(&self) -> Json {
+        Json::Null
+    }
+
+    /// Restores the state given by `save_state` when resuming from a checkpoint
+    fn load_state(&mut self, _state: &Json) -> Result<(), DecoderError> {
+        Ok(())
+    }
+}
+
+/// Object-safe form of `Experiment`, so that experiments can be chosen at runtime.
+/// `Box<AnyExperiment>` is an `Experiment` itself.
+pub trait AnyExperiment : Send + Sync {
+    fn population_settings(&self) -> pop::Settings;
+    fn mutation_settings(&self) -> mutation::Settings;
+    fn compat_coefficients(&self) -> genes::CompatCoefficients;
+
+    fn initial_genome(&self) -> genes::Genome;
+    fn node_names(&self) -> HashMap<genes::NodeId, String>;
+
+    fn evaluate(&self, rng: &mut StdRng, network: &nn::Network, organisms: &[pop::Organism]) -> f64;
     fn post_evaluation(&mut self, population: &pop::Population);
 
-    fn evaluate_to_string(&self, network: &mut nn::Network) -> String;
+    fn evaluate_to_string(&self, network: &nn::Network) -> String;
+    fn is_solved(&self, network: &nn::Network) -> bool;
+
+    fn save_state(&self) -> Json;
+    fn load_state(&mut self, state: &Json) -> Result<(), DecoderError>;
+
+    fn box_clone(&self) -> Box<AnyExperiment>;
+}
+
+impl<E: Experiment + Send + Sync + 'static> AnyExperiment for E {
+    fn population_settings(&self) -> pop::Settings { Experiment::population_settings(self) }
+    fn mutation_settings(&self) -> mutation::Settings { Experiment::mutation_settings(self) }
+    fn compat_coefficients(&self) -> genes::CompatCoefficients { Experiment::compat_coefficients(self) }
+
+    fn initial_genome(&self) -> genes::Genome { Experiment::initial_genome(self) }
+    fn node_names(&self) -> HashMap<genes::NodeId, String> { Experiment::node_names(self) }
+
+    fn evaluate(&self, rng: &mut StdRng, network: &nn::Network, organisms: &[pop::Organism]) -> f64 {
+        Experiment::evaluate(self, rng, network, organisms)
+    }
+    fn post_evaluation(&mut self, population: &pop::Population) { Experiment::post_evaluation(self, population) }
+
+    fn evaluate_to_string(&self, network: &nn::Network) -> String { Experiment::evaluate_to_string(self, network) }
+    fn is_solved(&self, network: &nn::Network) -> bool { Experiment::is_solved(self, network) }
+
+    fn save_state(&self) -> Json { Experiment::save_state(self) }
+    fn load_state(&mut self, state: &Json) -> Result<(), DecoderError> { Experiment::load_state(self, state) }
+
+    fn box_clone(&self) -> Box<AnyExperiment> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<AnyExperiment> {
+    fn clone(&self) -> Box<AnyExperiment> {
+        (**self).box_clone()
+    }
+}
+
+impl Experiment for Box<AnyExperiment> {
+    fn population_settings(&self) -> pop::Settings { (**self).population_settings() }
+    fn mutation_settings(&self) -> mutation::Settings { (**self).mutation_settings() }
+    fn compat_coefficients(&self) -> genes::CompatCoefficients { (**self).compat_coefficients() }
+
+    fn initial_genome(&self) -> genes::Genome { (**self).initial_genome() }
+    fn node_names(&self) -> HashMap<genes::NodeId, String> { (**self).node_names() }
+
+    fn evaluate(&self, rng: &mut StdRng, network: &nn::Network, organisms: &[pop::Organism]) -> f64 {
+        (**self).evaluate(rng, network, organisms)
+    }
+    fn post_evaluation(&mut self, population: &pop::Population) { (**self).post_evaluation(population) }
+
+    fn evaluate_to_string(&self, network: &nn::Network) -> String { (**self).evaluate_to_string(network) }
+    fn is_solved(&self, network: &nn::Network) -> bool { (**self).is_solved(network) }
+
+    fn save_state(&self) -> Json { (**self).save_state() }
+    fn load_state(&mut self, state: &Json) -> Result<(), DecoderError> { (**self).load_state(state) }
+}
+
+/// Averages over the runs of a benchmark that found a solution
+#[derive(Clone, Debug)]
+pub struct BenchmarkResult {
+    pub num_runs: usize,
+    pub num_solved: usize,
+    pub mean_generations: f64, // Counting the initial population as generation 1, as NEAT reports it
+    pub mean_hidden_nodes: f64,
+    pub mean_links: f64, // Enabled links only
+}
+
+impl fmt::Display for BenchmarkResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Solved {} of {} runs. Means over the solved runs: generations {:.2}, hidden nodes {:.2}, links {:.2}",
+               self.num_solved, self.num_runs, self.mean_generations, self.mean_hidden_nodes, self.mean_links)
+    }
+}
+
+/// Runs `num_runs` independent evolutions of the experiment, each one until a solution is found
+/// or `max_generations` have passed. This gives a regression benchmark for changes to the algorithm.
+/// Fails if the experiment's settings are invalid.
+pub fn benchmark<E, R>(experiment: &E,
+                       evaluator: &eval::Evaluator,
+                       rng: &mut R,
+                       population_size: usize,
+                       max_generations: usize,
+                       num_runs: usize) -> Result<BenchmarkResult, pop::Error>
+    where E: Experiment + Send + Sync + 'static, R: rand::Rng {
+    let mut num_solved = 0;
+    let mut sum_generations = 0;
+    let mut sum_hidden_nodes = 0;
+    let mut sum_links = 0;
+
+    for _ in 0..num_runs {
+        let mut experiment = experiment.clone();
+        let mut population = try!(pop::Population::from_initial_genome(rng,
+                                                                       &experiment.population_settings(),
+                                                                       &experiment.mutation_settings(),
+                                                                       &experiment.compat_coefficients(),
+                                                                       &experiment.initial_genome(),
+                                                                       population_size));
+
+        for generation in 0..max_generations {
+            evaluator.evaluate(rng, &mut experiment, &mut population);
+
+            let solution = population.species.iter()
+                                     .flat_map(|species| species.organisms.iter())
+                                     .filter(|organism| experiment.is_solved(&organism.network))
+                                     .next()
+                                     .map(|organism| organism.genome.clone());
+
+            if let Some(genome) = solution {
+                num_solved += 1;
+                sum_generations += generation + 1;
+                sum_hidden_nodes += genome.nodes.iter()
+                                          .filter(|node| node.node_type == genes::NodeType::Hidden)
+                                          .count();
+                sum_links += genome.num_links();
+                break;
+            }
+
+            population.epoch(rng);
+        }
+    }
+
+    let mean = |sum: usize| if num_solved > 0 { sum as f64 / num_solved as f64 } else { 0.0 };
+
+    Ok(BenchmarkResult {
+        num_runs: num_runs,
+        num_solved: num_solved,
+        mean_generations: mean(sum_generations),
+        mean_hidden_nodes: mean(sum_hidden_nodes),
+        mean_links: mean(sum_links),
+    })
 }
@@ -1,9 +1,7 @@
 extern crate rand;
 
 use std::collections::HashMap;
-use rand::Rng;
 use rand::StdRng;
-use rand::SeedableRng;
 
 use genes;
 use nn;
@@ -12,8 +10,13 @@ use pop;
 use mutation;
 
 #[derive(Clone)]
-pub struct PoleExperiment;
+pub struct PoleExperiment {
+    pub max_steps: usize, // The pole counts as balanced after this many steps
+    num_runs: usize, // The first run starts in the classic state, the others in random states near it
+    seed: usize, // Used to generate the random start states
+}
 
+#[derive(Clone, PartialEq, Debug)]
 struct PoleState {
     x: f64,
     x_dot: f64,
@@ -26,6 +29,17 @@ enum Input {
     Right
 }
 
+// Bounds as in the pole simulator written by Richard Sutton and Charles Anderson
+const X_BOUND: f64 = 2.4;
+const TWELVE_DEGREES: f64 = 0.2094384;
+
+// Random start states deviate at most this much from the classic one in each variable,
+// so that every start can be balanced
+const START_DEVIATION: f64 = 0.05;
+
+// Only the first steps of a run are traced, a balanced pole does not look very interesting
+const MAX_TRACE_STEPS: usize = 1000;
+
 // As in the pole simulator written by Richard Sutton and Charles Anderson
 fn pole_step(state: &mut PoleState, input: Input) {
     const GRAVITY: f64 = 9.8;
@@ -54,28 +68,118 @@ fn pole_step(state: &mut PoleState, input: Input) {
     state.theta_dot += TAU * theta_acc;
 }
 
-fn initial_state() -> PoleState {
-    let mut rng = rand::thread_rng();
+/// The classic start state: cart centered, pole upright, everything at rest
+fn classic_state() -> PoleState {
+    PoleState { x: 0.0, x_dot: 0.0, theta: 0.0, theta_dot: 0.0 }
+}
+
+/// Start state of a run. The first run uses the classic state, later ones a random state near it.
+fn initial_state<R: rand::Rng>(rng: &mut R, run: usize) -> PoleState {
+    if run == 0 {
+        return classic_state();
+    }
 
     PoleState {
-        x: rng.gen_range(-2.4, 2.4),
-        x_dot: rng.gen_range(-1.0, 1.0),
-        theta: rng.gen_range(-0.2, 0.2),
-        theta_dot: rng.gen_range(-1.5, 1.5),
+        x: rng.gen_range(-START_DEVIATION, START_DEVIATION),
+        x_dot: rng.gen_range(-START_DEVIATION, START_DEVIATION),
+        theta: rng.gen_range(-START_DEVIATION, START_DEVIATION),
+        theta_dot: rng.gen_range(-START_DEVIATION, START_DEVIATION),
     }
 }
 
-fn network_input(state: &PoleState, network: &mut nn::Network) {
-    const TWELVE_DEGREES: f64 = 0.2094384;
+fn has_failed(state: &PoleState) -> bool {
+    state.x.abs() > X_BOUND || state.theta.abs() > TWELVE_DEGREES
+}
 
-    let input = vec![(0, state.x / 2.4),
+fn network_input(state: &PoleState, network: &nn::Network, net_state: &mut nn::State) -> Input {
+    let input = vec![(0, state.x / X_BOUND),
                      (1, state.x_dot),
-                     (2, state.theta),
+                     (2, state.theta / TWELVE_DEGREES),
                      (3, state.theta_dot)];
-    network.set_input(&input);
+    network.set_input(net_state, &input);
+
+    if network.is_feedforward() {
+        network.activate_feedforward(net_state);
+    } else {
+        for _ in 1..10 {
+            network.activate(net_state);
+        }
+    }
+
+    let output = network.get_output(net_state);
+
+    if output[0].1 > output[1].1 {
+        Input::Left
+    } else {
+        Input::Right
+    }
+}
+
+fn state_to_string(state: &PoleState) -> String {
+    format!("x: {:7.3}, x_dot: {:7.3}, theta: {:7.3}, theta_dot: {:7.3}",
+            state.x, state.x_dot, state.theta, state.theta_dot)
+}
+
+impl PoleExperiment {
+    pub fn new() -> PoleExperiment {
+        PoleExperiment {
+            max_steps: 100000,
+            num_runs: 1,
+            seed: 1337,
+        }
+    }
+
+    /// Evaluates each network over `num_runs` runs, with random start states generated from `seed`
+    pub fn with_runs(num_runs: usize, seed: usize) -> PoleExperiment {
+        assert!(num_runs > 0, "Need at least one run to evaluate a network");
+
+        PoleExperiment {
+            num_runs: num_runs,
+            seed: seed,
+            .. PoleExperiment::new()
+        }
+    }
+
+    /// Start states of the runs, generated from `seed`
+    fn start_states(&self) -> Vec<PoleState> {
+        let seed: &[_] = &[self.seed];
+        let mut rng: StdRng = rand::SeedableRng::from_seed(seed);
+
+        (0..self.num_runs).map(|run| initial_state(&mut rng, run)).collect()
+    }
+
+    /// Number of steps for which the pole is balanced in each of the runs
+    fn balanced_steps(&self, network: &nn::Network) -> Vec<usize> {
+        let mut net_state = network.new_state();
+
+        self.start_states().into_iter().map(|mut state| {
+            self.run(&mut state, network, &mut net_state, None)
+        }).collect()
+    }
+
+    /// Balances the pole starting in `state`, returning the number of steps until failure
+    fn run(&self, state: &mut PoleState, network: &nn::Network, net_state: &mut nn::State,
+           mut trace: Option<&mut String>) -> usize {
+        network.flush(net_state);
+
+        for steps in 0..self.max_steps {
+            if has_failed(state) {
+                return steps;
+            }
+
+            let input = network_input(state, network, net_state);
 
-    for _ in 1..10 {
-        network.activate();
+            if let Some(ref mut trace) = trace {
+                if steps < MAX_TRACE_STEPS {
+                    trace.push_str(&format!("{:6}: {} -> {}\n", steps, state_to_string(state),
+                                            match input { Input::Left => "left", Input::Right => "right" }));
+                }
+            }
+
+            pole_step(state, input);
+        }
+
+        self.max_steps
     }
 }
 
@@ -93,7 +197,10 @@ impl exp::Experiment for PoleExperiment {
     }
 
     fn initial_genome(&self) -> genes::Genome {
-        genes::Genome::initial_genome(4, 2, 0, true)
+        // Only the larger of the two outputs counts, so they need no squashing
+        let mut genome = genes::Genome::initial_genome(4, 2, 0, true);
+        genome.set_output_activation(genes::ActivationFunction::Identity);
+        genome
     }
 
     fn node_names(&self) -> HashMap<genes::NodeId, String> {
@@ -107,24 +214,107 @@ impl exp::Experiment for PoleExperiment {
         map
     }
 
-    fn evaluate(&self, network: &mut nn::Network, organisms: &[pop::Organism]) -> f64 {
-        const MAX_STEPS: usize = 100;
-        const THRESH: usize = 100;
+    /// The organism's own generator is not used: every network starts from the same states,
+    /// so that fitness compares networks rather than the luck of their start states
+    fn evaluate(&self, _: &mut StdRng, network: &nn::Network, _organisms: &[pop::Organism]) -> f64 {
+        let num_steps = self.balanced_steps(network).iter().fold(0, |x, y| x + y);
+
+        num_steps as f64 / self.num_runs as f64
+    }
+
+    /// Solved if the pole is balanced until the end of every run
+    fn is_solved(&self, network: &nn::Network) -> bool {
+        self.balanced_steps(network).iter().all(|&steps| steps == self.max_steps)
+    }
+
+    fn evaluate_to_string(&self, network: &nn::Network) -> String {
+        let mut net_state = network.new_state();
+
+        let mut str = String::new();
+
+        for (run, mut state) in self.start_states().into_iter().enumerate() {
+            let mut trace = String::new();
+
+            let steps = self.run(&mut state, network, &mut net_state, Some(&mut trace));
 
-        let state = initial_state();
+            str.push_str(&format!("Run {}: balanced for {} of {} steps\n", run, steps, self.max_steps));
+            str.push_str(&trace);
 
-        for steps in 0..MAX_STEPS {
-            
+            if steps < self.max_steps {
+                str.push_str(&format!("Failed at {}\n", state_to_string(&state)));
+            }
+
+            str.push_str(&"=================\n");
         }
 
-        0.0
+        str
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use genes;
+    use genes::fixtures;
+    use nn;
+    use exp::Experiment;
+    use super::*;
+
+    /// A network that always pushes the cart to the right
+    fn push_right() -> nn::Network {
+        let mut genome = genes::Genome::initial_genome(4, 2, 0, false);
+        genome.set_output_activation(genes::ActivationFunction::Identity);
+        genome.add_link(fixtures::link(4, 6, 0));
+
+        nn::Network::from_genome(&genome).unwrap()
+    }
+
+    #[test]
+    fn fails_out_of_bounds() {
+        assert!(!has_failed(&classic_state()));
+        assert!(!has_failed(&PoleState { x: -2.3, theta: 0.2, .. classic_state() }));
+        assert!(has_failed(&PoleState { x: 2.5, .. classic_state() }));
+        assert!(has_failed(&PoleState { x: -2.5, .. classic_state() }));
+        assert!(has_failed(&PoleState { theta: 0.21, .. classic_state() }));
+        assert!(has_failed(&PoleState { theta: -0.21, .. classic_state() }));
     }
 
-    fn evaluate_to_string(&self, network: &mut nn::Network) -> String {
-        "".to_string()
+    #[test]
+    fn run_ends_when_pole_falls() {
+        let experiment = PoleExperiment::new();
+        let network = push_right();
+        let mut net_state = network.new_state();
+        let mut state = classic_state();
+
+        let steps = experiment.run(&mut state, &network, &mut net_state, None);
+
+        assert!(steps > 0 && steps < experiment.max_steps, "{}", steps);
+        assert!(has_failed(&state));
+        assert!(state.theta < 0.0, "{}", state_to_string(&state)); // Pushing right tips the pole to the left
+        assert!(!experiment.is_solved(&network));
+        assert_eq!(experiment.evaluate(&mut rand::SeedableRng::from_seed(&[1usize][..]), &network, &[]), steps as f64);
     }
 
-    fn post_evaluation(&mut self, population: &pop::Population) {
+    #[test]
+    fn run_stops_at_max_steps() {
+        let experiment = PoleExperiment { max_steps: 5, .. PoleExperiment::with_runs(3, 7) };
+        let network = push_right();
+
+        assert_eq!(experiment.balanced_steps(&network), vec![5, 5, 5]);
+        assert!(experiment.is_solved(&network));
+        assert_eq!(experiment.evaluate(&mut rand::SeedableRng::from_seed(&[1usize][..]), &network, &[]), 5.0);
+    }
+
+    #[test]
+    fn start_states_depend_on_seed_only() {
+        let states = PoleExperiment::with_runs(5, 42).start_states();
+
+        assert_eq!(states.len(), 5);
+        assert_eq!(states[0], classic_state());
+        assert!(states[1..].iter().all(|state| *state != classic_state() &&
+                                       [state.x, state.x_dot, state.theta, state.theta_dot].iter()
+                                           .all(|value| value.abs() <= START_DEVIATION)));
 
+        assert_eq!(PoleExperiment::with_runs(5, 42).start_states(), states);
+        assert!(PoleExperiment::with_runs(5, 43).start_states() != states);
     }
 }
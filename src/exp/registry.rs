@@ -0,0 +1,97 @@
+use config;
+use exp;
+use exp::AnyExperiment;
+
+/// An experiment that can be looked up by name
+pub struct Entry {
+    pub name: &'static str,
+    pub description: &'static str,
+    constructor: fn() -> Box<AnyExperiment>,
+}
+
+impl Entry {
+    /// Creates the experiment
+    pub fn new_experiment(&self) -> Box<AnyExperiment> {
+        (self.constructor)()
+    }
+
+    /// The settings the experiment is evolved with unless a config file or `--set` changes them
+    pub fn default_config(&self) -> config::Config {
+        let experiment = self.new_experiment();
+
+        config::Config {
+            population: experiment.population_settings(),
+            mutation: experiment.mutation_settings(),
+            compat: experiment.compat_coefficients(),
+        }
+    }
+}
+
+fn xor() -> Box<AnyExperiment> { Box::new(exp::xor::XorExperiment) }
+fn pole() -> Box<AnyExperiment> { Box::new(exp::pole::PoleExperiment::new()) }
+fn doublepole() -> Box<AnyExperiment> { Box::new(exp::doublepole::DoublePoleExperiment::new(true)) }
+fn doublepole_nv() -> Box<AnyExperiment> { Box::new(exp::doublepole::DoublePoleExperiment::new(false)) }
+fn roadgame() -> Box<AnyExperiment> { Box::new(exp::roadgame::RoadGameExperiment) }
+fn tictactoe() -> Box<AnyExperiment> { Box::new(exp::tictactoe::exp::TicTacToeExperiment::new()) }
+
+pub static EXPERIMENTS: &'static [Entry] = &[
+    Entry {
+        name: "xor",
+        description: "Compute exclusive or of two inputs, which needs a hidden node",
+        constructor: xor,
+    },
+    Entry {
+        name: "pole",
+        description: "Balance a pole on a cart",
+        constructor: pole,
+    },
+    Entry {
+        name: "doublepole",
+        description: "Balance two poles on a cart, with velocities as inputs",
+        constructor: doublepole,
+    },
+    Entry {
+        name: "doublepole-nv",
+        description: "Balance two poles on a cart without velocities, using Gruau's damping fitness",
+        constructor: doublepole_nv,
+    },
+    Entry {
+        name: "roadgame",
+        description: "Dodge obstacles on a road",
+        constructor: roadgame,
+    },
+    Entry {
+        name: "tictactoe",
+        description: "Play tic-tac-toe against fixed strategies and the population",
+        constructor: tictactoe,
+    },
+];
+
+pub fn find(name: &str) -> Option<&'static Entry> {
+    EXPERIMENTS.iter().find(|entry| entry.name == name)
+}
+
+pub fn names() -> Vec<&'static str> {
+    EXPERIMENTS.iter().map(|entry| entry.name).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_unique() {
+        let mut names = names();
+        names.sort();
+        names.dedup();
+
+        assert_eq!(names.len(), EXPERIMENTS.len());
+    }
+
+    #[test]
+    fn default_configs_are_valid() {
+        for entry in EXPERIMENTS.iter() {
+            assert!(entry.default_config().validate(150).is_empty(), "{} has invalid settings", entry.name);
+        }
+    }
+}
@@ -86,7 +86,7 @@ fn road_game_step(state: &mut GameState, input: Option<MoveInput>) {
     }
 }
 
-fn network_input(state: &GameState, network: &mut nn::Network) -> Option<MoveInput> {
+fn network_input(state: &GameState, network: &nn::Network, net_state: &mut nn::State) -> Option<MoveInput> {
     let mut input = Vec::new();
     let mut i = 0;
 
@@ -119,13 +119,17 @@ fn network_input(state: &GameState, network: &mut nn::Network) -> Option<MoveInp
     //let x_value = state.player_x as f64 / (ROAD_WIDTH-1) as f64 * 2.0 - 1.0;
     //input.push((i, x_value));
 
-    network.set_input(&input);
+    network.set_input(net_state, &input);
 
-    for _ in 1..10 {
-        network.activate();
+    if network.is_feedforward() {
+        network.activate_feedforward(net_state);
+    } else {
+        for _ in 1..10 {
+            network.activate(net_state);
+        }
     }
 
-    let out_value = network.get_output()[0].1;
+    let out_value = network.get_output(net_state)[0].1;
 
     if out_value > 0.5 {
         Some(MoveInput::Right)
@@ -212,20 +216,21 @@ impl exp::Experiment for RoadGameExperiment {
         map
     }
 
-    fn evaluate(&self, network: &mut nn::Network, organisms: &[pop::Organism]) -> f64 {
+    fn evaluate(&self, _: &mut StdRng, network: &nn::Network, _organisms: &[pop::Organism]) -> f64 {
         let max_steps = 10000;
         let num_runs = 500;
         let mut num_steps = 0;
 
         let seed: &[_] = &[1337];
         let mut rng: StdRng = rand::SeedableRng::from_seed(seed);
+        let mut net_state = network.new_state();
 
         for _ in 0..num_runs {
             let mut state = initial_state(rng.gen::<usize>());
-            network.flush();
+            network.flush(&mut net_state);
 
             for _ in 0..max_steps {
-                let input = network_input(&state, network);
+                let input = network_input(&state, network, &mut net_state);
                 road_game_step(&mut state, input);
                 num_steps += 1;
                 if state.hit_now {
@@ -237,7 +242,7 @@ impl exp::Experiment for RoadGameExperiment {
         (num_steps as f64 / num_runs as f64).powf(2.0)
     }
 
-    fn evaluate_to_string(&self, network: &mut nn::Network) -> String {
+    fn evaluate_to_string(&self, network: &nn::Network) -> String {
         let max_steps = 10000;
         let num_runs = 500;
         let mut num_steps = 0;
@@ -247,18 +252,17 @@ impl exp::Experiment for RoadGameExperiment {
         let mut rng: StdRng = rand::SeedableRng::from_seed(seed);
 
         let mut str = String::new();
-
-        network.flush();
+        let mut net_state = network.new_state();
 
         for _ in 0..num_runs {
             let mut state = initial_state(rng.gen::<usize>());
-            network.flush();
+            network.flush(&mut net_state);
 
             for _ in 0..max_steps {
                 str.push_str(&state_to_string(&state));
                 str.push_str(&"---\n");
 
-                let input = network_input(&state, network);
+                let input = network_input(&state, network, &mut net_state);
                 road_game_step(&mut state, input);
                 num_steps += 1;
                 if state.hit_now {
@@ -273,9 +277,5 @@ impl exp::Experiment for RoadGameExperiment {
 
         format!("Steps per run: {}\n", num_steps as f64 / num_runs as f64) + &str
     }
-
-    fn post_evaluation(&mut self, population: &pop::Population) {
-
-    }
 }
 
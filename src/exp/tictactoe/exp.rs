@@ -1,10 +1,14 @@
 use std::collections::HashMap;
+use rand::StdRng;
+use rustc_serialize::Decodable;
+use rustc_serialize::json::{self, DecoderError, Json};
 
 use genes;
 use nn;
 use exp;
 use pop;
 use mutation;
+use parse;
 
 use exp::tictactoe::game::*;
 use exp::tictactoe::strats::*;
@@ -24,12 +28,30 @@ pub struct TicTacToeExperiment {
     generation: usize
 }
 
+/// The part of the experiment that changes during evolution, stored in checkpoints
+#[derive(RustcEncodable, RustcDecodable)]
+struct State {
+    generation: usize,
+    champions: Vec<pop::Organism>,
+    last_update: usize,
+}
+
 pub struct NetworkStrategy<'a> {
-    pub network: &'a mut nn::Network
+    pub network: &'a nn::Network,
+    pub state: nn::State,
+}
+
+impl<'a> NetworkStrategy<'a> {
+    pub fn new(network: &'a nn::Network) -> NetworkStrategy<'a> {
+        NetworkStrategy {
+            network: network,
+            state: network.new_state(),
+        }
+    }
 }
 
 impl<'a> Strategy for NetworkStrategy<'a> {
-    fn get_move(&mut self, me: Player, state: &GameState) -> (usize, usize) {
+    fn get_move(&mut self, _: &mut StdRng, me: Player, state: &GameState) -> (usize, usize) {
         let mut input = Vec::new();
         let mut i = 0;
 
@@ -50,15 +72,19 @@ impl<'a> Strategy for NetworkStrategy<'a> {
             }
         }
 
-        self.network.flush();
-        self.network.set_input(&input);
+        self.network.flush(&mut self.state);
+        self.network.set_input(&mut self.state, &input);
 
-        for _ in 1..20 {
-            self.network.activate();
+        if self.network.is_feedforward() {
+            self.network.activate_feedforward(&mut self.state);
+        } else {
+            for _ in 1..20 {
+                self.network.activate(&mut self.state);
+            }
         }
 
         // Find legal output with highest activation
-        let output = self.network.get_output();  
+        let output = self.network.get_output(&self.state);  
         let mut highest_move = None;
         let mut highest_activation = 0.0;
 
@@ -84,17 +110,17 @@ impl<'a> Strategy for NetworkStrategy<'a> {
     }
 }
 
-pub fn score_network<Other: Strategy>(network: &mut nn::Network, other: &mut Other, runs: usize) -> f64 {
+pub fn score_network<Other: Strategy>(rng: &mut StdRng, network: &nn::Network, other: &mut Other, runs: usize) -> f64 {
     let mut total_score = 0.0; 
-    let mut strategy = NetworkStrategy { network: network };
+    let mut strategy = NetworkStrategy::new(network);
 
     // Alternate who starts first
     let mut player = Player::X;
 
     for _ in 0..2*runs {
         let outcome = match player {
-            Player::X => play(&mut strategy, other, false),
-            Player::O => play(other, &mut strategy, false)
+            Player::X => play(rng, &mut strategy, other, false),
+            Player::O => play(rng, other, &mut strategy, false)
         };
 
         let score = match outcome {
@@ -118,10 +144,10 @@ pub fn score_network<Other: Strategy>(network: &mut nn::Network, other: &mut Oth
     total_score
 }
 
-pub fn score_network_vs_network(network1: &mut nn::Network, network2: &mut nn::Network) -> f64 {
-    let mut strategy2 = NetworkStrategy { network: network2 };
+pub fn score_network_vs_network(rng: &mut StdRng, network1: &nn::Network, network2: &nn::Network) -> f64 {
+    let mut strategy2 = NetworkStrategy::new(network2);
 
-    score_network(network1, &mut strategy2, 1)
+    score_network(rng, network1, &mut strategy2, 1)
 }
 
 impl HallOfFame {
@@ -191,40 +217,34 @@ impl exp::Experiment for TicTacToeExperiment {
         map
     }
 
-    fn evaluate(&self, network: &mut nn::Network, organisms: &[pop::Organism]) -> f64 {
+    fn evaluate(&self, rng: &mut StdRng, network: &nn::Network, organisms: &[pop::Organism]) -> f64 {
         if self.generation % 200 < 100 {
-            let vs_fixed = (score_network(network, &mut BestStrategy { forkable: false }, 100) + 
-                            score_network(network, &mut BestStrategy { forkable: true }, 100) + 
-                            score_network(network, &mut RandomStrategy, 100) +
-                            score_network(network, &mut CenterStrategy, 100) +
-                            score_network(network, &mut BadStrategy, 100)) / 500.0;
+            let vs_fixed = (score_network(rng, network, &mut BestStrategy { forkable: false }, 100) + 
+                            score_network(rng, network, &mut BestStrategy { forkable: true }, 100) + 
+                            score_network(rng, network, &mut RandomStrategy, 100) +
+                            score_network(rng, network, &mut CenterStrategy, 100) +
+                            score_network(rng, network, &mut BadStrategy, 100)) / 500.0;
             vs_fixed.powi(2)
         } else {
             // Play against all the other organisms
             let mut vs_pop = 0.0;
 
             for organism in organisms {
-                // TODO: To avoid having to clone here, maybe separate a network's definition from its
-                // activation state
-                let mut network2 = organism.network.clone();
-
-                vs_pop += score_network_vs_network(network, &mut network2) / organisms.len() as f64;
+                vs_pop += score_network_vs_network(rng, network, &organism.network) / organisms.len() as f64;
             }
 
             // Play against hall of fame
             let mut vs_hof = 0.0;
 
             for organism in self.hall_of_fame.champions.iter() {
-                let mut network2 = organism.network.clone();
-
-                vs_hof += score_network_vs_network(network, &mut network2) / self.hall_of_fame.champions.len() as f64;
+                vs_hof += score_network_vs_network(rng, network, &organism.network) / self.hall_of_fame.champions.len() as f64;
             }
 
             (vs_pop + vs_hof).powi(2)
         }
     }
 
-    fn evaluate_to_string(&self, network: &mut nn::Network) -> String {
+    fn evaluate_to_string(&self, network: &nn::Network) -> String {
         "".to_string()
     }
 
@@ -232,4 +252,24 @@ impl exp::Experiment for TicTacToeExperiment {
         self.hall_of_fame.update(population);
         self.generation += 1;
     }
+
+    fn save_state(&self) -> Json {
+        let state = State {
+            generation: self.generation,
+            champions: self.hall_of_fame.champions.clone(),
+            last_update: self.hall_of_fame.last_update,
+        };
+
+        parse::json(&json::encode(&state).unwrap()).unwrap()
+    }
+
+    fn load_state(&mut self, state: &Json) -> Result<(), DecoderError> {
+        let state: State = try!(Decodable::decode(&mut json::Decoder::new(state.clone())));
+
+        self.generation = state.generation;
+        self.hall_of_fame.champions = state.champions;
+        self.hall_of_fame.last_update = state.last_update;
+
+        Ok(())
+    }
 }
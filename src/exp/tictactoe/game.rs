@@ -1,3 +1,5 @@
+use rand::StdRng;
+
 pub const HOW_TO_WIN: &'static [[(usize, usize); 3]] = &[
     [(0,0),(0,1),(0,2)],
     [(1,0),(1,1),(1,2)],
@@ -34,7 +36,7 @@ impl GameState {
 }
 
 pub trait Strategy {
-    fn get_move(&mut self, me: Player, state: &GameState) -> (usize, usize);
+    fn get_move(&mut self, rng: &mut StdRng, me: Player, state: &GameState) -> (usize, usize);
 }
 
 pub fn initial_state() -> GameState {
@@ -65,14 +67,14 @@ pub fn print_state(state: &GameState) {
     println!("---");
 }
 
-pub fn play(strat_x: &mut Strategy, strat_o: &mut Strategy, print: bool) -> Option<Player> {
+pub fn play(rng: &mut StdRng, strat_x: &mut Strategy, strat_o: &mut Strategy, print: bool) -> Option<Player> {
     let mut state = initial_state();
     let mut turn = Player::X;
 
     for _ in 0..9 {
         let (move_x, move_y) = match turn {
-            Player::X => strat_x.get_move(turn, &state),
-            Player::O => strat_o.get_move(turn, &state)
+            Player::X => strat_x.get_move(rng, turn, &state),
+            Player::O => strat_o.get_move(rng, turn, &state)
         };
         assert!(move_x < 3 && move_y < 3);
         assert!(state.field[move_x][move_y].is_none());
@@ -1,7 +1,7 @@
 use std::io;
 use std::io::Write;
-use rand;
 use rand::Rng;
+use rand::StdRng;
 
 use exp::tictactoe::game::*;
 
@@ -16,7 +16,7 @@ pub struct BestStrategy {
 }
 
 impl Strategy for InputStrategy {
-    fn get_move(&mut self, me: Player, state: &GameState) -> (usize, usize) {
+    fn get_move(&mut self, _rng: &mut StdRng, me: Player, state: &GameState) -> (usize, usize) {
         loop {
             print!("Move {}: ", match me { Player::X => "X", Player::O => "O" });
             io::stdout().flush();
@@ -53,7 +53,7 @@ impl Strategy for InputStrategy {
 }
 
 impl Strategy for RandomStrategy {
-    fn get_move(&mut self, me: Player, state: &GameState) -> (usize, usize) {
+    fn get_move(&mut self, rng: &mut StdRng, me: Player, state: &GameState) -> (usize, usize) {
         let mut legal_moves = vec![];
 
         for x in 0..3 {
@@ -64,25 +64,22 @@ impl Strategy for RandomStrategy {
             }
         }
 
-        let mut rng = rand::thread_rng();
         *rng.choose(&legal_moves).unwrap()
     }
 }
 
 impl Strategy for CenterStrategy {
-    fn get_move(&mut self, me: Player, state: &GameState) -> (usize, usize) {
+    fn get_move(&mut self, rng: &mut StdRng, me: Player, state: &GameState) -> (usize, usize) {
         if state.field[1][1].is_none() {
             (1, 1)
         } else {
-            RandomStrategy.get_move(me, state)
+            RandomStrategy.get_move(rng, me, state)
         }
     }
 }
 
 impl Strategy for BadStrategy {
-    fn get_move(&mut self, me: Player, state: &GameState) -> (usize, usize) {
-        let mut rng = rand::thread_rng();
-
+    fn get_move(&mut self, rng: &mut StdRng, me: Player, state: &GameState) -> (usize, usize) {
         let sides = vec![(0, 1), (1, 0), (2, 1), (1, 2)];
         let free_sides = sides 
                              .into_iter()
@@ -117,7 +114,7 @@ impl Strategy for BadStrategy {
 }
 
 impl Strategy for BestStrategy {
-    fn get_move(&mut self, me: Player, state: &GameState) -> (usize, usize) {
+    fn get_move(&mut self, rng: &mut StdRng, me: Player, state: &GameState) -> (usize, usize) {
         // http://programmers.stackexchange.com/questions/213559/algorithm-to-create-an-tictactoe-game-ai
 
         let not_me = match me {
@@ -125,8 +122,6 @@ impl Strategy for BestStrategy {
             Player::O => Player::X
         };
 
-        let mut rng = rand::thread_rng();
- 
         // 1. If there are two in a row, complete it
         let count_fields = |state: &GameState, player, fields: &[(usize, usize); 3]| fields.iter().fold(0, |count, &(x, y)| {
             if state.field[x][y] == player {
@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+use rand::StdRng;
+
+use genes;
+use nn;
+use exp;
+use pop;
+use mutation;
+
+#[derive(Clone)]
+pub struct XorExperiment;
+
+const CASES: &'static [(bool, bool)] = &[(false, false), (false, true), (true, false), (true, true)];
+
+fn to_f(x: bool) -> f64 {
+    if x { 1.0 } else { -1.0 }
+}
+
+fn network_output(network: &nn::Network, net_state: &mut nn::State, x: bool, y: bool) -> f64 {
+    network.flush(net_state);
+    network.set_input(net_state, &vec![(0, to_f(x)), (1, to_f(y))]);
+
+    if network.is_feedforward() {
+        network.activate_feedforward(net_state);
+    } else {
+        for _ in 1..10 {
+            network.activate(net_state);
+        }
+    }
+
+    network.get_output(net_state)[0].1
+}
+
+impl exp::Experiment for XorExperiment {
+    fn population_settings(&self) -> pop::Settings {
+        pop::STANDARD_SETTINGS
+    }
+
+    fn mutation_settings(&self) -> mutation::Settings {
+        // Structure and weights change as fast as in the original NEAT XOR experiment
+        mutation::Settings {
+            new_node_prob: 0.03,
+            change_link_weights_power: 2.5,
+            .. mutation::STANDARD_SETTINGS
+        }
+    }
+
+    fn compat_coefficients(&self) -> genes::CompatCoefficients {
+        genes::STANDARD_COMPAT_COEFFICIENTS
+    }
+
+    fn initial_genome(&self) -> genes::Genome {
+        // The targets are -1 and 1, the range of the default tanh output
+        genes::Genome::initial_genome(2, 1, 2, true)
+    }
+
+    fn node_names(&self) -> HashMap<genes::NodeId, String> {
+        let mut map = HashMap::new();
+
+        map.insert(0, "X".to_string());
+        map.insert(1, "Y".to_string());
+        map.insert(2, "B".to_string());
+        map.insert(3, "O".to_string());
+
+        map
+    }
+
+    fn evaluate(&self, _rng: &mut StdRng, network: &nn::Network, _organisms: &[pop::Organism]) -> f64 {
+        let mut net_state = network.new_state();
+
+        let sum_error = CASES.iter().map(|&(x, y)| {
+            let output = network_output(network, &mut net_state, x, y);
+            (output - to_f(x != y)).abs() / 2.0
+        }).fold(0.0, |x, y| x + y);
+
+        (4.0 - sum_error).powf(2.0)
+    }
+
+    /// Solved if all four outputs are on the correct side of zero
+    fn is_solved(&self, network: &nn::Network) -> bool {
+        let mut net_state = network.new_state();
+
+        CASES.iter().all(|&(x, y)| {
+            let output = network_output(network, &mut net_state, x, y);
+            (output > 0.0) == (x != y)
+        })
+    }
+
+    fn evaluate_to_string(&self, network: &nn::Network) -> String {
+        let mut net_state = network.new_state();
+        let mut str = String::new();
+
+        for &(x, y) in CASES.iter() {
+            let output = network_output(network, &mut net_state, x, y);
+            str.push_str(&format!("{},{} -> {} vs {}\n", x, y, output, to_f(x != y)));
+        }
+
+        str
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use genes;
+    use genes::fixtures;
+    use nn;
+    use exp::Experiment;
+    use super::*;
+
+    fn rng() -> StdRng {
+        SeedableRng::from_seed(&[1usize][..])
+    }
+
+    fn link(from_id: genes::NodeId, to_id: genes::NodeId, weight: f64, innovation: usize) -> genes::Link {
+        genes::Link { weight: weight, .. fixtures::link(from_id, to_id, innovation) }
+    }
+
+    /// Hidden node 4 is on if both inputs are, hidden node 5 if both are off.
+    /// The output is on if neither hidden node is.
+    fn xor_network() -> nn::Network {
+        let genome = fixtures::genome(vec![fixtures::node(4, genes::NodeType::Hidden),
+                                           fixtures::node(5, genes::NodeType::Hidden)],
+                                      vec![link(0, 4, 10.0, 0), link(1, 4, 10.0, 1), link(2, 4, -10.0, 2),
+                                           link(0, 5, -10.0, 3), link(1, 5, -10.0, 4), link(2, 5, -10.0, 5),
+                                           link(4, 3, -10.0, 6), link(5, 3, -10.0, 7), link(2, 3, -10.0, 8)]);
+
+        nn::Network::from_genome(&genome).unwrap()
+    }
+
+    #[test]
+    fn xor_network_solves() {
+        let network = xor_network();
+
+        assert!(XorExperiment.evaluate(&mut rng(), &network, &[]) > 15.99);
+        assert!(XorExperiment.is_solved(&network));
+    }
+
+    #[test]
+    fn constant_network_does_not_solve() {
+        // The bias link of the initial genome has weight 0, so the output is always 0
+        let network = nn::Network::from_genome(&genes::Genome::initial_genome(2, 1, 0, true)).unwrap();
+
+        assert_eq!(XorExperiment.evaluate(&mut rng(), &network, &[]), 4.0);
+        assert!(!XorExperiment.is_solved(&network));
+    }
+}
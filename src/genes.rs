@@ -1,13 +1,135 @@
+use std::collections::BTreeMap;
 use std::collections::BTreeSet;
 use std::collections::HashMap;
 use std::process::Command;
 use std::io::prelude::*;
 use std::io;
+use std::error;
+use std::fmt;
 use std::fs::File;
 use std::path::Path;
 
+use rustc_serialize::{Decodable, Decoder, Encodable, Encoder};
 use rustc_serialize::json::{self, ToJson, Json};
 
+use parse;
+use validation;
+
+/// Version of the genome file format written by `Genome::save`.
+/// Version 0 are the files without a header, written before `Node` had an activation function.
+pub const FORMAT_VERSION: u64 = 1;
+
+/// Reasons why loading or saving a genome or a population checkpoint can fail
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Parse(String),
+    UnsupportedVersion(u64),
+    Integrity(Vec<Problem>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref err) => write!(f, "I/O error: {}", err),
+            Error::Parse(ref msg) => write!(f, "Invalid file: {}", msg),
+            Error::UnsupportedVersion(version) =>
+                write!(f, "File has version {}, which is newer than the supported versions", version),
+            Error::Integrity(ref problems) => {
+                try!(write!(f, "Malformed genome:"));
+
+                for problem in problems.iter() {
+                    try!(write!(f, " {}.", problem));
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Io(_) => "I/O error",
+            Error::Parse(_) => "invalid file",
+            Error::UnsupportedVersion(_) => "unsupported file version",
+            Error::Integrity(_) => "malformed genome",
+        }
+    }
+}
+
+/// A problem with a genome found by `Genome::validate`
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Problem {
+    DuplicateNodeId(NodeId),
+    DuplicateLink { from_id: NodeId, to_id: NodeId },
+    DanglingLink { innovation: usize, from_id: NodeId, to_id: NodeId },
+    LinkIntoInput { innovation: usize, to_id: NodeId },
+    UnsortedInnovation { innovation: usize }, // Not larger than the previous link's innovation
+    UnreachableOutput(NodeId),
+    NoInputs,
+    NoOutputs,
+}
+
+impl Problem {
+    /// Fatal problems break the assumptions of mutation, mating and `nn::Network`.
+    /// An output that cannot be reached from the inputs just stays inactive, so it is not fatal.
+    pub fn is_fatal(&self) -> bool {
+        match *self {
+            Problem::UnreachableOutput(_) => false,
+            _ => true
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Problem::DuplicateNodeId(id) =>
+                write!(f, "More than one node has id {}", id),
+            Problem::DuplicateLink { from_id, to_id } =>
+                write!(f, "More than one link from {} to {}", from_id, to_id),
+            Problem::DanglingLink { innovation, from_id, to_id } =>
+                write!(f, "Link {} from {} to {} connects a missing node", innovation, from_id, to_id),
+            Problem::LinkIntoInput { innovation, to_id } =>
+                write!(f, "Link {} leads into input or bias node {}", innovation, to_id),
+            Problem::UnsortedInnovation { innovation } =>
+                write!(f, "Links are not sorted by innovation at {}", innovation),
+            Problem::UnreachableOutput(id) =>
+                write!(f, "Output {} cannot be reached from the inputs", id),
+            Problem::NoInputs =>
+                write!(f, "There are no input nodes"),
+            Problem::NoOutputs =>
+                write!(f, "There are no output nodes"),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl From<json::ParserError> for Error {
+    fn from(err: json::ParserError) -> Error {
+        Error::Parse(err.to_string())
+    }
+}
+
+impl From<json::DecoderError> for Error {
+    fn from(err: json::DecoderError) -> Error {
+        Error::Parse(err.to_string())
+    }
+}
+
+impl From<json::EncoderError> for Error {
+    fn from(err: json::EncoderError) -> Error {
+        Error::Parse(err.to_string())
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, RustcEncodable, RustcDecodable)]
 pub enum NodeType {
     Input,
@@ -16,12 +138,71 @@ pub enum NodeType {
     Bias,
 }
 
+/// The function a node applies to the weighted sum of its inputs
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, RustcEncodable, RustcDecodable)]
+pub enum ActivationFunction {
+    Tanh,
+    SteepenedSigmoid,
+    ReLU,
+    Identity,
+    Step,
+    Gaussian,
+    Sine,
+    Abs,
+}
+
+pub const ACTIVATION_FUNCTIONS: &'static [ActivationFunction] = &[
+    ActivationFunction::Tanh,
+    ActivationFunction::SteepenedSigmoid,
+    ActivationFunction::ReLU,
+    ActivationFunction::Identity,
+    ActivationFunction::Step,
+    ActivationFunction::Gaussian,
+    ActivationFunction::Sine,
+    ActivationFunction::Abs,
+];
+
+/// A set of activation functions, e.g. the ones that mutations may assign to hidden nodes
+#[derive(Clone, Copy, PartialEq, Eq, Debug, RustcEncodable, RustcDecodable)]
+pub struct ActivationFunctionSet {
+    pub tanh: bool,
+    pub steepened_sigmoid: bool,
+    pub relu: bool,
+    pub identity: bool,
+    pub step: bool,
+    pub gaussian: bool,
+    pub sine: bool,
+    pub abs: bool,
+}
+
+impl ActivationFunctionSet {
+    pub fn contains(&self, function: ActivationFunction) -> bool {
+        match function {
+            ActivationFunction::Tanh => self.tanh,
+            ActivationFunction::SteepenedSigmoid => self.steepened_sigmoid,
+            ActivationFunction::ReLU => self.relu,
+            ActivationFunction::Identity => self.identity,
+            ActivationFunction::Step => self.step,
+            ActivationFunction::Gaussian => self.gaussian,
+            ActivationFunction::Sine => self.sine,
+            ActivationFunction::Abs => self.abs,
+        }
+    }
+
+    pub fn to_vec(&self) -> Vec<ActivationFunction> {
+        ACTIVATION_FUNCTIONS.iter().filter(|function| self.contains(**function))
+                                   .cloned()
+                                   .collect()
+    }
+}
+
 pub type NodeId = usize;
 
 #[derive(Clone, Copy, PartialEq, PartialOrd, Debug, RustcEncodable, RustcDecodable)]
 pub struct Node {
     pub id: NodeId,
     pub node_type: NodeType,
+    pub activation: ActivationFunction, // Ignored for input and bias nodes
 }
 
 #[derive(Clone, Copy, PartialEq, PartialOrd, Debug, RustcEncodable, RustcDecodable)]
@@ -34,19 +215,102 @@ pub struct Link {
     pub is_recurrent: bool,
 }
 
-#[derive(Clone, Debug, RustcEncodable, RustcDecodable)]
+/// Nodes and links must only be added or removed with the methods of `Genome`, so that its index stays
+/// consistent. Other fields of the genes, such as link weights, can be changed directly.
+#[derive(Clone, Debug)]
 pub struct Genome {
     pub nodes: Vec<Node>,
     pub links: Vec<Link>, // Sorted by innovation number in increasing order
+
+    index: Index,
 }
 
-#[derive(Clone, Copy, Debug)]
+/// Positions of genes in a genome's `nodes` and `links`.
+/// These do not change when a link is enabled or disabled, so the adjacency lists contain disabled links too.
+#[derive(Clone, Debug)]
+struct Index {
+    nodes: HashMap<NodeId, usize>,
+    links: HashMap<(NodeId, NodeId), usize>,
+    successors: HashMap<NodeId, Vec<usize>>,
+    predecessors: HashMap<NodeId, Vec<usize>>,
+}
+
+impl Index {
+    fn new() -> Index {
+        Index {
+            nodes: HashMap::new(),
+            links: HashMap::new(),
+            successors: HashMap::new(),
+            predecessors: HashMap::new(),
+        }
+    }
+
+    fn insert_node(&mut self, index: usize, node: &Node) {
+        self.nodes.entry(node.id).or_insert(index);
+    }
+
+    fn insert_link(&mut self, index: usize, link: &Link) {
+        self.links.entry((link.from_id, link.to_id)).or_insert(index);
+        self.successors.entry(link.from_id).or_insert(vec![]).push(index);
+        self.predecessors.entry(link.to_id).or_insert(vec![]).push(index);
+    }
+
+    fn rebuild_links(&mut self, links: &[Link]) {
+        self.links.clear();
+        self.successors.clear();
+        self.predecessors.clear();
+
+        for (index, link) in links.iter().enumerate() {
+            self.insert_link(index, link);
+        }
+    }
+}
+
+// The index is not stored, but rebuilt when decoding
+impl Encodable for Genome {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_struct("Genome", 2, |s| {
+            try!(s.emit_struct_field("nodes", 0, |s| self.nodes.encode(s)));
+            s.emit_struct_field("links", 1, |s| self.links.encode(s))
+        })
+    }
+}
+
+impl Decodable for Genome {
+    fn decode<D: Decoder>(d: &mut D) -> Result<Genome, D::Error> {
+        d.read_struct("Genome", 2, |d| {
+            let nodes = try!(d.read_struct_field("nodes", 0, Decodable::decode));
+            let links = try!(d.read_struct_field("links", 1, Decodable::decode));
+
+            Ok(Genome::from_genes(nodes, links))
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, RustcEncodable, RustcDecodable)]
 pub struct CompatCoefficients {
     pub disjoint: f64,
     pub excess: f64,
     pub weight_diff: f64,
 }
 
+impl CompatCoefficients {
+    pub fn validate(&self) -> Vec<validation::InvalidSetting> {
+        let mut invalid = vec![];
+
+        for &(field, value) in [("disjoint", self.disjoint),
+                                ("excess", self.excess),
+                                ("weight_diff", self.weight_diff)].iter() {
+            if !(value >= 0.0 && value.is_finite()) {
+                invalid.push(validation::InvalidSetting::new(field, format!(
+                    "{} is not a weight, it must be finite and not negative", value)));
+            }
+        }
+
+        invalid
+    }
+}
+
 pub static STANDARD_COMPAT_COEFFICIENTS: CompatCoefficients = CompatCoefficients {
     disjoint: 1.0,
     excess: 1.0,
@@ -94,31 +358,68 @@ pub fn compatibility(c: &CompatCoefficients,
         }
     }
 
-    assert!(num_matching > 0);
+    // Genomes can share no links at all once links have been deleted
+    let mean_weight_diff = if num_matching > 0 { weight_diff / num_matching as f64 } else { 0.0 };
 
     return c.disjoint * num_disjoint as f64 +
            c.excess * num_excess as f64 +
-           c.weight_diff * (weight_diff / num_matching as f64);
+           c.weight_diff * mean_weight_diff;
 }
 
 impl Genome {
+    pub fn new() -> Genome {
+        Genome {
+            nodes: vec![],
+            links: vec![],
+            index: Index::new(),
+        }
+    }
+
+    /// Creates a genome from the given genes as they are, even if they do not form a valid genome
+    pub fn from_genes(nodes: Vec<Node>, links: Vec<Link>) -> Genome {
+        let mut genome = Genome {
+            nodes: nodes,
+            links: links,
+            index: Index::new(),
+        };
+
+        genome.rebuild_index();
+        genome
+    }
+
+    fn rebuild_index(&mut self) {
+        self.index = Index::new();
+
+        for (index, node) in self.nodes.iter().enumerate() {
+            self.index.insert_node(index, node);
+        }
+
+        self.index.rebuild_links(&self.links);
+    }
+
     pub fn initial_genome(num_inputs: usize, num_outputs: usize, num_connected: usize, bias_connected: bool) -> Genome {
         assert!(num_connected <= num_inputs);
 
-        let mut genome = Genome { nodes: vec![], links: vec![] };
+        let mut genome = Genome::new();
         let mut node_counter = 0;
         let mut innovation_counter = 0;
 
         for _ in 0..num_inputs {
-            genome.nodes.push(Node { id: node_counter, node_type: NodeType::Input });
+            genome.add_node(Node { id: node_counter,
+                                   node_type: NodeType::Input,
+                                   activation: ActivationFunction::Identity });
             node_counter += 1;
         }
 
-        genome.nodes.push(Node { id: node_counter, node_type: NodeType::Bias });
+        genome.add_node(Node { id: node_counter,
+                               node_type: NodeType::Bias,
+                               activation: ActivationFunction::Identity });
         node_counter += 1;
 
         for _ in 0..num_outputs {
-            genome.nodes.push(Node { id: node_counter, node_type: NodeType::Output });
+            genome.add_node(Node { id: node_counter,
+                                   node_type: NodeType::Output,
+                                   activation: ActivationFunction::Tanh });
 
             if bias_connected {
                 genome.add_link(Link {
@@ -151,30 +452,136 @@ impl Genome {
         genome
     }
 
+    /// Sets the activation function of all output nodes, e.g. `Identity` for regression tasks
+    pub fn set_output_activation(&mut self, activation: ActivationFunction) {
+        for node in self.nodes.iter_mut() {
+            if node.node_type == NodeType::Output {
+                node.activation = activation;
+            }
+        }
+    }
+
     pub fn assert_integrity(&self) {
-        for link in self.links.iter() {
-            assert!(self.is_link(link.from_id, link.to_id));
-            assert!(self.is_node(link.from_id));
-            assert!(self.is_node(link.to_id));
+        let problems = self.validate().into_iter()
+                                      .filter(|problem| problem.is_fatal())
+                                      .collect::<Vec<_>>();
+
+        assert!(problems.is_empty(), "Malformed genome: {:?}", problems);
+
+        // Genes added or removed without going through our methods would be missing from the index
+        assert_eq!(self.index.nodes.len(), self.nodes.len(), "Genome index is out of date");
+        assert_eq!(self.index.links.len(), self.links.len(), "Genome index is out of date");
+    }
+
+    /// Checks the genome for problems without panicking
+    pub fn validate(&self) -> Vec<Problem> {
+        let mut problems = vec![];
+
+        let mut node_types = HashMap::new();
+        for node in self.nodes.iter() {
+            if node_types.contains_key(&node.id) {
+                problems.push(Problem::DuplicateNodeId(node.id));
+            } else {
+                node_types.insert(node.id, node.node_type);
+            }
         }
 
-        for node in self.nodes.iter() { 
-            assert!(self.is_node(node.id));
+        let mut node_pairs = BTreeSet::new();
+        for (i, link) in self.links.iter().enumerate() {
+            if i > 0 && self.links[i-1].innovation >= link.innovation {
+                problems.push(Problem::UnsortedInnovation { innovation: link.innovation });
+            }
 
-            if node.node_type == NodeType::Input {
-                assert_eq!(self.predecessor_links(node.id).len(), 0);
+            if !node_pairs.insert((link.from_id, link.to_id)) {
+                problems.push(Problem::DuplicateLink { from_id: link.from_id, to_id: link.to_id });
+            }
+
+            match (node_types.get(&link.from_id), node_types.get(&link.to_id)) {
+                (Some(_), Some(&to_type)) =>
+                    if to_type == NodeType::Input || to_type == NodeType::Bias {
+                        problems.push(Problem::LinkIntoInput { innovation: link.innovation,
+                                                               to_id: link.to_id });
+                    },
+                _ =>
+                    problems.push(Problem::DanglingLink { innovation: link.innovation,
+                                                          from_id: link.from_id,
+                                                          to_id: link.to_id }),
             }
         }
 
-        {
-            //assert!(self.links.len() > 0);
+        if !self.nodes.iter().any(|node| node.node_type == NodeType::Input) {
+            problems.push(Problem::NoInputs);
+        }
+        if !self.nodes.iter().any(|node| node.node_type == NodeType::Output) {
+            problems.push(Problem::NoOutputs);
+        }
 
-            if self.links.len() > 0 {
-                for i in 0..self.links.len()-1 {
-                    assert!(self.links[i].innovation < self.links[i+1].innovation);
-                }
+        // Find the nodes that can be reached from an input using enabled links. An output fed only
+        // by the bias is constant, so it counts as unreachable.
+        let mut successors = HashMap::new();
+        for link in self.links.iter().filter(|link| link.enabled) {
+            successors.entry(link.from_id).or_insert(vec![]).push(link.to_id);
+        }
+
+        let mut reached = BTreeSet::new();
+        let mut queue = self.nodes.iter()
+                                  .filter(|node| node.node_type == NodeType::Input)
+                                  .map(|node| node.id)
+                                  .collect::<Vec<_>>();
+
+        while let Some(id) = queue.pop() {
+            if !reached.insert(id) {
+                continue;
+            }
+
+            if let Some(succ_ids) = successors.get(&id) {
+                queue.extend(succ_ids.iter().filter(|succ_id| !reached.contains(succ_id)));
+            }
+        }
+
+        for node in self.nodes.iter() {
+            if node.node_type == NodeType::Output && !reached.contains(&node.id) {
+                problems.push(Problem::UnreachableOutput(node.id));
             }
         }
+
+        problems
+    }
+
+    /// Removes the genes that cause fatal problems: nodes with duplicate ids,
+    /// links that are duplicate, dangling or lead into inputs, and links with duplicate innovation numbers.
+    /// The remaining links are sorted by innovation number.
+    pub fn repair(&mut self) {
+        let mut node_types = HashMap::new();
+        self.nodes.retain(|node| {
+            if node_types.contains_key(&node.id) {
+                false
+            } else {
+                node_types.insert(node.id, node.node_type);
+                true
+            }
+        });
+
+        let mut node_pairs = BTreeSet::new();
+        self.links.retain(|link| {
+            match (node_types.get(&link.from_id), node_types.get(&link.to_id)) {
+                (Some(_), Some(&to_type)) if to_type != NodeType::Input && to_type != NodeType::Bias =>
+                    node_pairs.insert((link.from_id, link.to_id)),
+                _ => false
+            }
+        });
+
+        self.links.sort_by(|a, b| a.innovation.cmp(&b.innovation));
+        self.links.dedup_by_key(|link| link.innovation);
+
+        self.rebuild_index();
+    }
+
+    pub fn add_node(&mut self, new_node: Node) {
+        assert!(!self.is_node(new_node.id));
+
+        self.nodes.push(new_node);
+        self.index.insert_node(self.nodes.len() - 1, &new_node);
     }
 
     /// Adds a new link to the genome, keeping the list sorted by innovation number
@@ -185,16 +592,54 @@ impl Genome {
 
         //println!("{:?}", self.links.iter().map(|l| l.innovation).collect::<Vec<usize>>());
 
-        // Try to find the position of the first link having a bigger innovation number than the new link
-        match self.links.iter().position(|link| link.innovation > new_link.innovation) {
-            Some(position) =>
-                self.links.insert(position, new_link),
-            None =>
-                self.links.push(new_link)
+        // Find the position of the first link having a bigger innovation number than the new link.
+        // Usually, this is the end of the list, e.g. when building the genome of an offspring.
+        let position = match self.links.binary_search_by(|link| link.innovation.cmp(&new_link.innovation)) {
+            Ok(position) => position + 1,
+            Err(position) => position
+        };
+
+        if position == self.links.len() {
+            self.links.push(new_link);
+            self.index.insert_link(position, &new_link);
+        } else {
+            // The positions of all the following links change
+            self.links.insert(position, new_link);
+            self.index.rebuild_links(&self.links);
         }
         //println!("=> {:?}", self.links.iter().map(|l| l.innovation).collect::<Vec<usize>>());
     }
 
+    /// Removes the link from one node to another, if there is one
+    pub fn remove_link(&mut self, from_id: NodeId, to_id: NodeId) -> Option<Link> {
+        match self.index.links.get(&(from_id, to_id)).cloned() {
+            Some(index) => {
+                let link = self.links.remove(index);
+
+                // The positions of all the following links change
+                self.index.rebuild_links(&self.links);
+
+                Some(link)
+            },
+            None => None
+        }
+    }
+
+    /// Removes a node together with all the links leading into or out of it
+    pub fn remove_node(&mut self, id: NodeId) -> Option<Node> {
+        match self.index.nodes.get(&id).cloned() {
+            Some(index) => {
+                let node = self.nodes.remove(index);
+                self.links.retain(|link| link.from_id != id && link.to_id != id);
+
+                self.rebuild_index();
+
+                Some(node)
+            },
+            None => None
+        }
+    }
+
     pub fn num_nodes(&self) -> usize {
         self.nodes.len() //self.nodes.iter().filter(|node| node.enabled).count();
     }
@@ -204,47 +649,43 @@ impl Genome {
     }
 
     pub fn is_link(&self, from_id: NodeId, to_id: NodeId) -> bool {
-        let count = self.links.iter()
-                              .filter(|link| link.from_id == from_id && link.to_id == to_id)
-                              .count();
-        assert!(count == 0 || count == 1, "More than one link between the same nodes");
-
-        return count == 1;
+        self.index.links.contains_key(&(from_id, to_id))
     }
 
     pub fn get_link(&self, from_id: NodeId, to_id: NodeId) -> Option<&Link> {
-        self.links.iter().filter(|link| link.from_id == from_id && link.to_id == to_id).next()
+        self.index.links.get(&(from_id, to_id)).map(|&index| &self.links[index])
     }
 
     pub fn is_node(&self, id: NodeId) -> bool {
-        let count = self.nodes.iter().filter(|node| node.id == id).count();
-        assert!(count == 0 || count == 1, "More than one node with the same id");
-
-        return count == 1;
+        self.index.nodes.contains_key(&id)
     }
 
     pub fn get_node(&self, id: NodeId) -> Option<&Node> {
-        self.nodes.iter().filter(|node| node.id == id).next()
+        self.index.nodes.get(&id).map(|&index| &self.nodes[index])
     }
 
     pub fn successor_links(&self, from_id: NodeId) -> Vec<&Link> {
-        // This kind of stuff is quite inefficient with the representation,
-        // will need to see if it has to get optimized
         assert!(self.is_node(from_id));
 
-        return self.links.iter()
-                         .filter(|link| link.enabled && link.from_id == from_id)
-                         .collect();
+        match self.index.successors.get(&from_id) {
+            Some(indices) => indices.iter()
+                                    .map(|&index| &self.links[index])
+                                    .filter(|link| link.enabled)
+                                    .collect(),
+            None => vec![]
+        }
     }
 
     pub fn predecessor_links(&self, to_id: NodeId) -> Vec<&Link> {
-        // This kind of stuff is quite inefficient with the representation,
-        // will need to see if it has to get optimized
         assert!(self.is_node(to_id));
 
-        return self.links.iter()
-                         .filter(|link| link.enabled && link.to_id == to_id)
-                         .collect();
+        match self.index.predecessors.get(&to_id) {
+            Some(indices) => indices.iter()
+                                    .map(|&index| &self.links[index])
+                                    .filter(|link| link.enabled)
+                                    .collect(),
+            None => vec![]
+        }
     }
 
     /// Checks if a new link would be considered recurrent in the network.
@@ -293,6 +734,11 @@ impl Genome {
                 name = name + &"<";
             }
 
+            if (node.node_type == NodeType::Hidden || node.node_type == NodeType::Output) &&
+               node.activation != ActivationFunction::Tanh {
+                name = name + &format!(" {:?}", node.activation);
+            }
+
             "\"".to_string() + &name + &"\""
         };
 
@@ -349,16 +795,298 @@ impl Genome {
         }
     }
 
-    pub fn save(&self, path: &Path) {
-        let mut f = File::create(path).unwrap();
-        f.write_all(json::encode(&self).unwrap().as_bytes()).unwrap();
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let mut file = BTreeMap::new();
+        file.insert("version".to_string(), FORMAT_VERSION.to_json());
+        file.insert("genome".to_string(), try!(parse::json(&try!(json::encode(&self))).map_err(Error::Parse)));
+
+        let mut f = try!(File::create(path));
+        try!(f.write_all(Json::Object(file).to_string().as_bytes()));
+
+        Ok(())
     }
     
-    pub fn load(path: &Path) -> Genome {
-        let mut f = File::open(path).unwrap();
+    pub fn load(path: &Path) -> Result<Genome, Error> {
+        let mut f = try!(File::open(path));
         let mut s = String::new();
-        f.read_to_string(&mut s).unwrap();
+        try!(f.read_to_string(&mut s));
+
+        Genome::from_json_str(&s)
+    }
+
+    /// Parses a genome in the format written by `save`, upgrading files of older versions
+    pub fn from_json_str(s: &str) -> Result<Genome, Error> {
+        let file = try!(parse::json(s).map_err(Error::Parse));
+
+        let (version, mut genome_json) = match file.find("version") {
+            Some(version) => {
+                let version = try!(version.as_u64().ok_or(Error::Parse("version is not a number".to_string())));
+                let genome_json = try!(file.find("genome").ok_or(Error::Parse("missing genome".to_string())));
+
+                (version, genome_json.clone())
+            },
+            None => (0, file) // No header yet
+        };
+
+        if version > FORMAT_VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+
+        upgrade_format(&mut genome_json, version);
+
+        let genome: Genome = try!(Decodable::decode(&mut json::Decoder::new(genome_json)));
+
+        let problems = genome.validate().into_iter()
+                                        .filter(|problem| problem.is_fatal())
+                                        .collect::<Vec<_>>();
+
+        if problems.is_empty() {
+            Ok(genome)
+        } else {
+            Err(Error::Integrity(problems))
+        }
+    }
+}
+
+/// Fills in the fields that were added to the genes after the given format version
+fn upgrade_format(genome_json: &mut Json, version: u64) {
+    if version < 1 {
+        // Activation functions were introduced. Before, every node used tanh.
+        let nodes = genome_json.as_object_mut()
+                               .and_then(|genome| genome.get_mut("nodes"))
+                               .and_then(|nodes| nodes.as_array_mut());
+
+        if let Some(nodes) = nodes {
+            for node in nodes.iter_mut() {
+                if let Json::Object(ref mut node) = *node {
+                    let activation = match node.get("node_type") {
+                        Some(&Json::String(ref node_type)) if node_type == "Input" || node_type == "Bias" =>
+                            ActivationFunction::Identity,
+                        _ => ActivationFunction::Tanh
+                    };
+
+                    if !node.contains_key("activation") {
+                        node.insert("activation".to_string(), Json::String(format!("{:?}", activation)));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Genes for the tests of this and the other modules
+#[cfg(test)]
+pub mod fixtures {
+    use super::*;
+
+    /// An enabled link with weight 1
+    pub fn link(from_id: NodeId, to_id: NodeId, innovation: usize) -> Link {
+        Link {
+            from_id: from_id,
+            to_id: to_id,
+            enabled: true,
+            innovation: innovation,
+            weight: 1.0,
+            is_recurrent: false,
+        }
+    }
+
+    pub fn node(id: NodeId, node_type: NodeType) -> Node {
+        Node { id: id, node_type: node_type, activation: ActivationFunction::Tanh }
+    }
+
+    /// Inputs 0 and 1, bias 2 and output 3, with the given extra nodes and links.
+    /// Built without the checks of `add_node` and `add_link`.
+    pub fn genome(extra_nodes: Vec<Node>, links: Vec<Link>) -> Genome {
+        let mut nodes = vec![node(0, NodeType::Input), node(1, NodeType::Input),
+                             node(2, NodeType::Bias), node(3, NodeType::Output)];
+        nodes.extend(extra_nodes);
+
+        Genome::from_genes(nodes, links)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::fixtures::*;
+
+    #[test]
+    fn validate_accepts_initial_genome() {
+        assert!(Genome::initial_genome(3, 2, 3, true).validate().is_empty());
+    }
+
+    #[test]
+    fn validate_finds_problems() {
+        let genome = genome(vec![node(3, NodeType::Hidden)],
+                            vec![link(0, 3, 2), link(0, 3, 1), link(0, 7, 3), link(3, 1, 4)]);
+
+        assert_eq!(genome.validate(),
+                   vec![Problem::DuplicateNodeId(3),
+                        Problem::UnsortedInnovation { innovation: 1 },
+                        Problem::DuplicateLink { from_id: 0, to_id: 3 },
+                        Problem::DanglingLink { innovation: 3, from_id: 0, to_id: 7 },
+                        Problem::LinkIntoInput { innovation: 4, to_id: 1 }]);
+    }
+
+    #[test]
+    fn unreachable_output_is_not_fatal() {
+        let problems = genome(vec![], vec![Link { enabled: false, .. link(0, 3, 0) }]).validate();
+
+        assert_eq!(problems, vec![Problem::UnreachableOutput(3)]);
+        assert!(!problems[0].is_fatal());
+    }
+
+    #[test]
+    fn output_reached_only_from_bias_is_unreachable() {
+        let problems = genome(vec![node(4, NodeType::Hidden)],
+                              vec![link(2, 4, 0), link(4, 3, 1)]).validate();
+
+        assert_eq!(problems, vec![Problem::UnreachableOutput(3)]);
+    }
+
+    #[test]
+    fn repair_removes_fatal_problems() {
+        let mut genome = genome(vec![node(3, NodeType::Hidden), node(4, NodeType::Hidden)],
+                                vec![link(0, 4, 5), link(4, 3, 6), link(0, 3, 1), link(0, 3, 2),
+                                     link(0, 7, 3), link(3, 1, 4), link(1, 3, 6)]);
+
+        genome.repair();
+
+        assert!(genome.validate().is_empty());
+        assert_eq!(genome.nodes.len(), 5);
+        assert_eq!(genome.links, vec![link(0, 3, 1), link(0, 4, 5), link(4, 3, 6)]);
+        assert!(genome.is_link(4, 3) && !genome.is_link(1, 3));
+        assert_eq!(genome.successor_links(0).len(), 2);
+    }
+
+    /// The index kept up to date by the methods of `Genome` must equal an index built from scratch
+    fn assert_index_consistent(genome: &Genome) {
+        let rebuilt = Genome::from_genes(genome.nodes.clone(), genome.links.clone());
+        let sorted = |lists: &HashMap<NodeId, Vec<usize>>| {
+            lists.iter()
+                 .filter(|&(_, indices)| !indices.is_empty())
+                 .map(|(&id, indices)| {
+                     let mut indices = indices.clone();
+                     indices.sort();
+                     (id, indices)
+                 })
+                 .collect::<BTreeMap<_, _>>()
+        };
+
+        assert_eq!(genome.index.nodes, rebuilt.index.nodes);
+        assert_eq!(genome.index.links, rebuilt.index.links);
+        assert_eq!(sorted(&genome.index.successors), sorted(&rebuilt.index.successors));
+        assert_eq!(sorted(&genome.index.predecessors), sorted(&rebuilt.index.predecessors));
+    }
+
+    #[test]
+    fn index_after_add_link() {
+        let mut genome = genome(vec![node(4, NodeType::Hidden)], vec![]);
+
+        // Out of order, so that links are inserted before existing ones
+        for &(from_id, to_id, innovation) in [(0, 4, 5), (4, 3, 2), (1, 4, 7), (2, 3, 0), (4, 4, 3)].iter() {
+            genome.add_link(link(from_id, to_id, innovation));
+            assert_index_consistent(&genome);
+        }
+
+        assert_eq!(genome.links.iter().map(|link| link.innovation).collect::<Vec<_>>(), vec![0, 2, 3, 5, 7]);
+        assert_eq!(genome.get_link(1, 4).map(|link| link.innovation), Some(7));
+        assert_eq!(genome.predecessor_links(4).len(), 3);
+    }
+
+    #[test]
+    fn index_after_remove_link() {
+        let mut genome = genome(vec![node(4, NodeType::Hidden)],
+                                vec![link(2, 3, 0), link(4, 3, 2), link(0, 4, 5), link(1, 4, 7)]);
+
+        assert_eq!(genome.remove_link(4, 3).map(|link| link.innovation), Some(2));
+        assert_index_consistent(&genome);
+        assert_eq!(genome.remove_link(4, 3), None);
+
+        assert!(!genome.is_link(4, 3));
+        assert_eq!(genome.get_link(1, 4).map(|link| link.innovation), Some(7));
+        assert!(genome.successor_links(4).is_empty());
+    }
+
+    #[test]
+    fn index_after_remove_node() {
+        let mut genome = genome(vec![node(4, NodeType::Hidden), node(5, NodeType::Hidden)],
+                                vec![link(2, 3, 0), link(4, 3, 2), link(0, 4, 5), link(4, 4, 6), link(1, 5, 7),
+                                     link(5, 3, 8)]);
+
+        assert_eq!(genome.remove_node(4).map(|node| node.id), Some(4));
+        assert_index_consistent(&genome);
+        assert_eq!(genome.remove_node(4), None);
+
+        assert!(!genome.is_node(4));
+        assert_eq!(genome.links.iter().map(|link| link.innovation).collect::<Vec<_>>(), vec![0, 7, 8]);
+        assert_eq!(genome.get_node(5).map(|node| node.id), Some(5));
+        assert_eq!(genome.predecessor_links(3).len(), 2);
+    }
+
+    #[test]
+    fn upgrade_format_from_version_0() {
+        let old = r#"{"nodes": [{"id": 0, "node_type": "Input"}, {"id": 1, "node_type": "Bias"},
+                                {"id": 2, "node_type": "Output"}, {"id": 3, "node_type": "Hidden"}],
+                      "links": [{"from_id": 0, "to_id": 3, "enabled": true, "innovation": 0,
+                                 "weight": 0.5, "is_recurrent": false}]}"#;
+
+        let genome = Genome::from_json_str(old).unwrap();
+
+        assert_eq!(genome.nodes.iter().map(|node| node.activation).collect::<Vec<_>>(),
+                   vec![ActivationFunction::Identity, ActivationFunction::Identity,
+                        ActivationFunction::Tanh, ActivationFunction::Tanh]);
+        assert_eq!(genome.links, vec![Link { weight: 0.5, .. link(0, 3, 0) }]);
+    }
+
+    #[test]
+    fn upgrade_format_keeps_current_version() {
+        let mut genome_json = Json::from_str(
+            r#"{"nodes": [{"id": 0, "node_type": "Input", "activation": "ReLU"}], "links": []}"#).unwrap();
+        let current = genome_json.clone();
+
+        upgrade_format(&mut genome_json, FORMAT_VERSION);
+
+        assert_eq!(genome_json, current);
+    }
+
+    #[test]
+    fn from_json_str_rejects_newer_and_malformed_files() {
+        match Genome::from_json_str(&format!(r#"{{"version": {}, "genome": {{}}}}"#, FORMAT_VERSION + 1)) {
+            Err(Error::UnsupportedVersion(version)) => assert_eq!(version, FORMAT_VERSION + 1),
+            other => panic!("expected unsupported version, got {:?}", other)
+        }
+
+        let dangling = r#"{"version": 1, "genome": {"nodes": [{"id": 0, "node_type": "Input", "activation": "Identity"}],
+                                                   "links": [{"from_id": 0, "to_id": 5, "enabled": true, "innovation": 0,
+                                                              "weight": 0.5, "is_recurrent": false}]}}"#;
+        match Genome::from_json_str(dangling) {
+            Err(Error::Integrity(problems)) =>
+                assert_eq!(problems, vec![Problem::DanglingLink { innovation: 0, from_id: 0, to_id: 5 }]),
+            other => panic!("expected malformed genome, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn save_and_load_keep_weights_exactly() {
+        let mut genome = genes_with_weights(&[0.3, 0.1 + 0.2, -1.0 / 3.0]);
+        genome.links[0].enabled = false;
+        let path = ::std::env::temp_dir().join(format!("neat-genome-test-{}.json", ::std::process::id()));
+
+        genome.save(&path).unwrap();
+        let loaded = Genome::load(&path).unwrap();
+        ::std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.nodes, genome.nodes);
+        assert_eq!(loaded.links, genome.links);
+    }
+
+    fn genes_with_weights(weights: &[f64]) -> Genome {
+        let links = weights.iter().enumerate()
+                           .map(|(innovation, &weight)| Link { weight: weight, .. link(innovation, 3, innovation) })
+                           .collect();
 
-        json::decode(&s).unwrap()
+        genome(vec![], links)
     }
 }
@@ -7,3 +7,8 @@ pub mod nn;
 pub mod pop;
 pub mod mating;
 pub mod exp;
+pub mod eval;
+pub mod stats;
+pub mod config;
+pub mod parse;
+pub mod validation;
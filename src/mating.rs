@@ -1,32 +1,133 @@
 extern crate rand;
 
-use rand::Rng;
-
 use genes;
+use mutation;
+
+/// A way of combining the genes of two parents into an offspring.
+/// Takes any random number generator as a trait object, so operators can be chosen at runtime.
+pub trait Crossover {
+    /// `genome_a` is the fitter parent, or the smaller one if `equal_fitness` is true
+    fn mate(&self, rng: &mut rand::Rng,
+            genome_a: &genes::Genome, genome_b: &genes::Genome,
+            equal_fitness: bool) -> genes::Genome;
+}
+
+/// Aligns the links of the parents by innovation number. Matching links are taken from a random parent,
+/// or averaged. Disjoint and excess links are taken from the fitter parent.
+#[derive(Clone, Copy, Debug)]
+pub struct Multipoint {
+    pub average: bool, // Average the weights of matching links
+    pub keep_disjoint_of_equal: bool, // Take disjoint and excess links from both parents if they are equally fit
+    pub disable_rule: mutation::DisableRule,
+    pub disable_prob: mutation::Prob,
+}
+
+/// Takes the links of one parent up to a random innovation number, and the links of the other parent after it
+#[derive(Clone, Copy, Debug)]
+pub struct SinglePoint;
+
+impl Multipoint {
+    /// Multipoint crossover as configured by the crossover settings
+    pub fn from_settings(settings: &mutation::Settings, average: bool) -> Multipoint {
+        Multipoint {
+            average: average,
+            keep_disjoint_of_equal: settings.keep_disjoint_of_equal_parents,
+            disable_rule: settings.disable_rule,
+            disable_prob: settings.disable_prob,
+        }
+    }
+}
+
+impl Crossover for Multipoint {
+    fn mate(&self, mut rng: &mut rand::Rng,
+            genome_a: &genes::Genome, genome_b: &genes::Genome,
+            equal_fitness: bool) -> genes::Genome {
+        align(&mut rng, genome_a, genome_b, self, equal_fitness && self.keep_disjoint_of_equal)
+    }
+}
+
+impl Crossover for SinglePoint {
+    fn mate(&self, mut rng: &mut rand::Rng,
+            genome_a: &genes::Genome, genome_b: &genes::Genome,
+            _: bool) -> genes::Genome {
+        singlepoint(&mut rng, genome_a, genome_b)
+    }
+}
+
+/// The crossover operators configured by the crossover settings, with their relative probabilities.
+/// Built once per generation rather than for every offspring.
+pub struct Operators {
+    operators: Vec<(mutation::Prob, Box<Crossover>)>,
+}
+
+impl Operators {
+    pub fn new(settings: &mutation::Settings) -> Operators {
+        Operators {
+            operators: vec![(settings.multipoint_prob, Box::new(Multipoint::from_settings(settings, false))),
+                            (settings.multipoint_avg_prob, Box::new(Multipoint::from_settings(settings, true))),
+                            (settings.singlepoint_prob, Box::new(SinglePoint))],
+        }
+    }
+
+    /// Mates two genomes with one of the operators, chosen according to their probabilities
+    pub fn crossover<R: rand::Rng>(&self, rng: &mut R,
+                                   genome_a: &genes::Genome, genome_b: &genes::Genome,
+                                   equal_fitness: bool) -> genes::Genome {
+        let sum_prob = self.operators.iter().fold(0.0, |sum, &(prob, _)| sum + prob);
+        let mut choice = rng.next_f64() * sum_prob;
+
+        for &(prob, ref operator) in self.operators.iter() {
+            if choice < prob {
+                return operator.mate(rng, genome_a, genome_b, equal_fitness);
+            }
+
+            choice -= prob;
+        }
 
-/// Mate two genomes by aligning their links by innovation number.
-/// `parent_a` is assumed to be the better genome.
-pub fn multipoint<R: rand::Rng>(rng: &mut R, genome_a: &genes::Genome, genome_b: &genes::Genome) -> genes::Genome {
+        // Rounding may leave a tiny rest, which goes to the last operator that can be chosen
+        let &(_, ref operator) = self.operators.iter().rev().find(|&&(prob, _)| prob > 0.0).unwrap();
+        operator.mate(rng, genome_a, genome_b, equal_fitness)
+    }
+}
+
+/// Returns true if the first parent should be treated as the better one in crossover.
+/// Ties in fitness go to the smaller genome, as in original NEAT, and then to a random parent.
+pub fn is_first_better<R: rand::Rng>(rng: &mut R,
+                                     fitness_a: f64, genome_a: &genes::Genome,
+                                     fitness_b: f64, genome_b: &genes::Genome) -> bool {
+    if fitness_a != fitness_b {
+        fitness_a > fitness_b
+    } else if genome_a.links.len() != genome_b.links.len() {
+        genome_a.links.len() < genome_b.links.len()
+    } else {
+        rng.gen::<bool>()
+    }
+}
+
+fn align<R: rand::Rng>(rng: &mut R, genome_a: &genes::Genome, genome_b: &genes::Genome,
+                       multipoint: &Multipoint, keep_disjoint_b: bool) -> genes::Genome {
     // Indices into the link genes of parent_a and parent_b
     let mut i = 0;
     let mut j = 0;
 
-    let mut offspring = genes::Genome { nodes: vec![], links: vec![] };
-
     // Add all nodes from the better genome so we don't lose any inputs
-    offspring.nodes = genome_a.nodes.clone();
-
-    while i < genome_a.links.len() {
-        let link_a = &genome_a.links[i];
+    let mut offspring = genes::Genome::from_genes(genome_a.nodes.clone(), vec![]);
 
+    while i < genome_a.links.len() || (keep_disjoint_b && j < genome_b.links.len()) {
         // Chose a gene to insert
         let choice =
-            if j == genome_b.links.len() {
+            if i == genome_a.links.len() {
+                j += 1;
+
+                // End of better genome reached - take excess from worse genome
+                Some((genome_b.links[j-1], genome_b))
+            } else if j == genome_b.links.len() {
                 i += 1;
 
                 // End of worse genome reached - take excess from better genome
-                Some((link_a.clone(), genome_a))
+                Some((genome_a.links[i-1], genome_a))
             } else {
+                let link_a = &genome_a.links[i];
                 let link_b = &genome_b.links[j];
 
                 // Check for a match in innovation numbers
@@ -37,20 +138,27 @@ pub fn multipoint<R: rand::Rng>(rng: &mut R, genome_a: &genes::Genome, genome_b:
                     i += 1;
                     j += 1;
 
-                    // We have a match, select randomly
+                    // We have a match, select randomly or average
                     let (gene, genome) =
-                        if rng.gen::<bool>() {
-                            (link_a, genome_a)
+                        if multipoint.average {
+                            (genes::Link { weight: (link_a.weight + link_b.weight) / 2.0, .. *link_a }, genome_a)
+                        } else if rng.gen::<bool>() {
+                            (*link_a, genome_a)
                         } else {
-                            (link_b, genome_b)
+                            (*link_b, genome_b)
                         };
 
-                    // If the link is disabled in a parent, probably disable as well
-                    if (!link_a.enabled || !link_b.enabled) &&
-                       rng.next_f64() < 0.75 {
-                        Some((genes::Link { enabled: false, .. *gene }, genome))
+                    // If the link is disabled in the parents, probably disable as well
+                    let disabled = match multipoint.disable_rule {
+                        mutation::DisableRule::EitherParent => !link_a.enabled || !link_b.enabled,
+                        mutation::DisableRule::BothParents => !link_a.enabled && !link_b.enabled,
+                        mutation::DisableRule::ChosenParent => !gene.enabled,
+                    };
+
+                    if disabled && rng.next_f64() < multipoint.disable_prob {
+                        Some((genes::Link { enabled: false, .. gene }, genome))
                     } else {
-                        Some((*gene, genome))
+                        Some((gene, genome))
                     }
                 } else if link_a.innovation < link_b.innovation {
                     i += 1;
@@ -60,31 +168,191 @@ pub fn multipoint<R: rand::Rng>(rng: &mut R, genome_a: &genes::Genome, genome_b:
                 } else { // link_a.innovation > link_b.innovation
                     j += 1;
 
-                    // Skip disjoint genes from worse genome
-                    None
+                    // Skip disjoint genes from worse genome, unless the parents are equally good
+                    if keep_disjoint_b {
+                        Some((*link_b, genome_b))
+                    } else {
+                        None
+                    }
                 }
-            }; 
+            };
 
         match choice {
-            Some((gene, genome)) => {
-                if offspring.is_link(gene.from_id, gene.to_id) {
-                    continue;
-                }
+            Some((gene, genome)) => add_gene(&mut offspring, gene, genome),
+            None => continue
+        }
+    }
 
-                // Create the link's nodes if they don't exist yet
-                if !offspring.is_node(gene.from_id) {
-                    offspring.nodes.push(genome.get_node(gene.from_id).unwrap().clone());
-                }
-                if !offspring.is_node(gene.to_id) {
-                    offspring.nodes.push(genome.get_node(gene.to_id).unwrap().clone());
-                }
+    offspring
+}
 
-                offspring.add_link(gene);
-            },
+/// Takes the links of `genome_a` with innovation numbers below a random crossover point,
+/// and the links of `genome_b` from the point on
+pub fn singlepoint<R: rand::Rng>(rng: &mut R, genome_a: &genes::Genome, genome_b: &genes::Genome) -> genes::Genome {
+    let mut offspring = genes::Genome::from_genes(genome_a.nodes.clone(), vec![]);
 
-            None => continue
-        }
+    if genome_a.links.is_empty() {
+        return offspring;
+    }
+
+    let point = genome_a.links[rng.gen_range(0, genome_a.links.len())].innovation;
+
+    for link in genome_a.links.iter().filter(|link| link.innovation < point) {
+        add_gene(&mut offspring, *link, genome_a);
+    }
+    for link in genome_b.links.iter().filter(|link| link.innovation >= point) {
+        add_gene(&mut offspring, *link, genome_b);
     }
 
     offspring
 }
+
+/// Adds a link taken from `genome` to the offspring, together with its nodes
+fn add_gene(offspring: &mut genes::Genome, gene: genes::Link, genome: &genes::Genome) {
+    if offspring.is_link(gene.from_id, gene.to_id) {
+        return;
+    }
+
+    // Create the link's nodes if they don't exist yet
+    if !offspring.is_node(gene.from_id) {
+        offspring.add_node(*genome.get_node(gene.from_id).unwrap());
+    }
+    if !offspring.is_node(gene.to_id) {
+        offspring.add_node(*genome.get_node(gene.to_id).unwrap());
+    }
+
+    offspring.add_link(gene);
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{SeedableRng, StdRng};
+
+    use genes;
+    use genes::fixtures;
+    use mutation;
+    use super::*;
+
+    fn rng() -> StdRng {
+        SeedableRng::from_seed(&[42usize][..])
+    }
+
+    /// Two inputs, bias and one output, connected by the links with the given innovation numbers,
+    /// all with the same weight. Innovation `i` goes from node `i` to the output.
+    fn genome(innovations: &[usize], weight: f64) -> genes::Genome {
+        let links = innovations.iter()
+                               .map(|&innovation| genes::Link { weight: weight, .. fixtures::link(innovation, 3, innovation) })
+                               .collect();
+
+        fixtures::genome(vec![], links)
+    }
+
+    fn innovations(genome: &genes::Genome) -> Vec<usize> {
+        genome.links.iter().map(|link| link.innovation).collect()
+    }
+
+    #[test]
+    fn multipoint_takes_disjoint_links_of_better_parent() {
+        let multipoint = Multipoint::from_settings(&mutation::STANDARD_SETTINGS, false);
+        let offspring = multipoint.mate(&mut rng(), &genome(&[0, 1], 1.0), &genome(&[0, 2], 1.0), false);
+
+        assert_eq!(innovations(&offspring), vec![0, 1]);
+        assert!(offspring.validate().is_empty());
+    }
+
+    #[test]
+    fn multipoint_keeps_disjoint_links_of_equal_parents() {
+        let multipoint = Multipoint::from_settings(&mutation::STANDARD_SETTINGS, false);
+        let offspring = multipoint.mate(&mut rng(), &genome(&[0, 1], 1.0), &genome(&[0, 2], 1.0), true);
+
+        assert_eq!(innovations(&offspring), vec![0, 1, 2]);
+        assert!(offspring.validate().is_empty());
+    }
+
+    #[test]
+    fn multipoint_avg_averages_matching_weights() {
+        let multipoint_avg = Multipoint::from_settings(&mutation::STANDARD_SETTINGS, true);
+        let offspring = multipoint_avg.mate(&mut rng(), &genome(&[0, 1], 1.0), &genome(&[0, 1], 3.0), false);
+
+        assert!(offspring.links.iter().all(|link| link.weight == 2.0));
+    }
+
+    #[test]
+    fn disable_rule_keeps_gene_if_roll_fails() {
+        let mut genome_a = genome(&[0], 1.0);
+        let mut genome_b = genome(&[0], 1.0);
+        genome_a.links[0].enabled = false;
+        genome_b.links[0].enabled = false;
+
+        let never = Multipoint { disable_prob: 0.0, .. Multipoint::from_settings(&mutation::STANDARD_SETTINGS, false) };
+        assert!(!never.mate(&mut rng(), &genome_a, &genome_b, false).links[0].enabled);
+
+        genome_a.links[0].enabled = true;
+
+        let always = Multipoint { disable_prob: 1.0, .. never };
+        assert!(!always.mate(&mut rng(), &genome_a, &genome_b, false).links[0].enabled);
+
+        let both = Multipoint { disable_rule: mutation::DisableRule::BothParents, .. always };
+        let mut rng = rng();
+        for _ in 0..10 {
+            let link = both.mate(&mut rng, &genome_a, &genome_b, false).links[0];
+            assert!(link == genome_a.links[0] || link == genome_b.links[0]);
+        }
+    }
+
+    #[test]
+    fn singlepoint_switches_parents_once() {
+        let genome_a = genome(&[0, 1, 2], 1.0);
+        let genome_b = genome(&[0, 1, 2], 2.0);
+        let mut rng = rng();
+
+        for _ in 0..20 {
+            let offspring = singlepoint(&mut rng, &genome_a, &genome_b);
+            let weights = offspring.links.iter().map(|link| link.weight).collect::<Vec<_>>();
+
+            assert_eq!(innovations(&offspring), vec![0, 1, 2]);
+            assert!(weights.windows(2).all(|pair| pair[0] <= pair[1]), "weights {:?}", weights);
+            assert_eq!(weights[2], 2.0);
+        }
+    }
+
+    #[test]
+    fn singlepoint_of_empty_genome_has_its_nodes() {
+        let offspring = singlepoint(&mut rng(), &genome(&[], 1.0), &genome(&[0, 1], 1.0));
+
+        assert_eq!(offspring.nodes.len(), 4);
+        assert!(offspring.links.is_empty());
+    }
+
+    #[test]
+    fn operators_choose_only_operators_with_probability() {
+        let settings = mutation::Settings {
+            multipoint_prob: 0.0,
+            multipoint_avg_prob: 1.0,
+            singlepoint_prob: 0.0,
+            .. mutation::STANDARD_SETTINGS
+        };
+        let operators = Operators::new(&settings);
+        let mut rng = rng();
+
+        for _ in 0..20 {
+            let offspring = operators.crossover(&mut rng, &genome(&[0, 1], 1.0), &genome(&[0, 1], 3.0), false);
+            assert!(offspring.links.iter().all(|link| link.weight == 2.0));
+        }
+    }
+
+    #[test]
+    fn is_first_better_breaks_ties_by_size() {
+        let small = genome(&[0], 1.0);
+        let large = genome(&[0, 1], 1.0);
+        let mut rng = rng();
+
+        assert!(is_first_better(&mut rng, 2.0, &large, 1.0, &small));
+        assert!(!is_first_better(&mut rng, 1.0, &small, 2.0, &large));
+        assert!(is_first_better(&mut rng, 1.0, &small, 1.0, &large));
+        assert!(!is_first_better(&mut rng, 1.0, &large, 1.0, &small));
+
+        let choices = (0..50).map(|_| is_first_better(&mut rng, 1.0, &small, 1.0, &small)).collect::<Vec<_>>();
+        assert!(choices.contains(&true) && choices.contains(&false));
+    }
+}
@@ -1,52 +1,195 @@
 extern crate rand;
 
-use std::collections::HashMap;
-use rand::Rng;
+use std::collections::{HashMap, HashSet};
+use std::f64;
+use rand::distributions::{IndependentSample, Normal};
 use genes;
+use validation;
 
 pub type Prob = f64;
 
-#[derive(Clone, Copy)]
+/// How the mate from another species is chosen in interspecies mating
+#[derive(Clone, Copy, PartialEq, Debug, RustcEncodable, RustcDecodable)]
+pub enum MateSelection {
+    Random, // Random elite of a random other species
+    FitnessWeighted, // Elite of any other species, with probability proportional to its fitness
+}
+
+/// How `mutate` changes link weights
+#[derive(Clone, Copy, PartialEq, Debug, RustcEncodable, RustcDecodable)]
+pub enum WeightMutation {
+    Standard, // As in original NEAT, see `change_link_weights_standard`
+    PerturbateSome, // Perturbate each link with probability `weight_mutation_rate`
+    ResetAll,
+}
+
+/// The distribution of the random values that perturbate or reset link weights
+#[derive(Clone, Copy, PartialEq, Debug, RustcEncodable, RustcDecodable)]
+pub enum WeightDistribution {
+    Uniform, // In (-power,power)
+    Gaussian, // With standard deviation `power`
+    Cauchy, // With scale `power`, which sometimes gives large changes
+}
+
+/// When a link that matches in both parents of a crossover is disabled in the offspring
+#[derive(Clone, Copy, PartialEq, Debug, RustcEncodable, RustcDecodable)]
+pub enum DisableRule {
+    EitherParent, // Disabled in at least one parent, as in original NEAT
+    BothParents, // Disabled in both parents
+    ChosenParent, // Disabled in the parent the link is taken from
+}
+
+#[derive(Clone, Copy, RustcEncodable, RustcDecodable)]
 pub struct Settings {
     // Probabilities for specific genome mutations
     pub new_node_prob: Prob,
     pub new_link_prob: Prob,
+    pub delete_link_prob: Prob,
+    pub delete_node_prob: Prob,
 
     pub change_link_weights_prob: Prob,
     pub change_link_weights_power: f64,
     pub uniform_perturbation_prob: Prob,
+    pub weight_mutation: WeightMutation,
+    pub weight_mutation_rate: Prob, // Passed to the `change_link_weights_*` function of `weight_mutation`
+    pub weight_distribution: WeightDistribution,
+    pub min_weight: f64,
+    pub max_weight: f64,
 
     pub recurrent_link_prob: Prob,
     pub self_link_prob: Prob, 
     pub toggle_enable_prob: Prob,
 
+    pub change_activation_prob: Prob,
+
+    // Activation functions that hidden nodes may have
+    pub activation_functions: genes::ActivationFunctionSet,
+
     // Probabilities for different kinds of reproduction
     pub mutate_only_prob: Prob,
     pub mutate_after_mating_prob: Prob,
     pub no_crossover_prob: Prob,
     pub interspecies_mating_prob: Prob,
+    pub interspecies_mate_selection: MateSelection,
+
+    // Relative probabilities of the crossover operators, see `mating::Operators`
+    pub multipoint_prob: Prob,
+    pub multipoint_avg_prob: Prob,
+    pub singlepoint_prob: Prob,
+    pub keep_disjoint_of_equal_parents: bool, // Multipoint crossover takes disjoint links from both parents if they are equally fit
+    pub disable_rule: DisableRule,
+    pub disable_prob: Prob, // Probability that a matching link is disabled when `disable_rule` applies
 }
 
 pub static STANDARD_SETTINGS: Settings =
     Settings {
         new_node_prob: 0.005,
         new_link_prob: 0.05,
+        delete_link_prob: 0.0,
+        delete_node_prob: 0.0,
 
         change_link_weights_prob: 0.75,
         change_link_weights_power: 0.5,
         uniform_perturbation_prob: 0.9,
+        weight_mutation: WeightMutation::PerturbateSome,
+        weight_mutation_rate: 0.3,
+        weight_distribution: WeightDistribution::Uniform,
+        min_weight: -8.0,
+        max_weight: 8.0,
 
         recurrent_link_prob: 0.03,
         self_link_prob: 0.5,
 
         toggle_enable_prob: 0.05,
 
+        change_activation_prob: 0.05,
+
+        activation_functions: genes::ActivationFunctionSet {
+            tanh: true,
+            steepened_sigmoid: false,
+            relu: false,
+            identity: false,
+            step: false,
+            gaussian: false,
+            sine: false,
+            abs: false,
+        },
+
         mutate_only_prob: 0.25,
         mutate_after_mating_prob: 0.8,
         no_crossover_prob: 0.25,
         interspecies_mating_prob: 0.001,
+        interspecies_mate_selection: MateSelection::Random,
+
+        multipoint_prob: 1.0,
+        multipoint_avg_prob: 0.0,
+        singlepoint_prob: 0.0,
+        keep_disjoint_of_equal_parents: true,
+        disable_rule: DisableRule::EitherParent,
+        disable_prob: 0.75,
     };                   
 
+impl Settings {
+    pub fn weight_change(&self) -> WeightChange {
+        WeightChange {
+            power: self.change_link_weights_power,
+            distribution: self.weight_distribution,
+            min_weight: self.min_weight,
+            max_weight: self.max_weight,
+        }
+    }
+
+    pub fn validate(&self) -> Vec<validation::InvalidSetting> {
+        let mut invalid = vec![];
+
+        for &(field, value) in [("new_node_prob", self.new_node_prob),
+                                ("new_link_prob", self.new_link_prob),
+                                ("delete_link_prob", self.delete_link_prob),
+                                ("delete_node_prob", self.delete_node_prob),
+                                ("change_link_weights_prob", self.change_link_weights_prob),
+                                ("uniform_perturbation_prob", self.uniform_perturbation_prob),
+                                ("weight_mutation_rate", self.weight_mutation_rate),
+                                ("recurrent_link_prob", self.recurrent_link_prob),
+                                ("self_link_prob", self.self_link_prob),
+                                ("toggle_enable_prob", self.toggle_enable_prob),
+                                ("change_activation_prob", self.change_activation_prob),
+                                ("mutate_only_prob", self.mutate_only_prob),
+                                ("mutate_after_mating_prob", self.mutate_after_mating_prob),
+                                ("no_crossover_prob", self.no_crossover_prob),
+                                ("interspecies_mating_prob", self.interspecies_mating_prob),
+                                ("multipoint_prob", self.multipoint_prob),
+                                ("multipoint_avg_prob", self.multipoint_avg_prob),
+                                ("singlepoint_prob", self.singlepoint_prob),
+                                ("disable_prob", self.disable_prob)].iter() {
+            validation::check_prob(&mut invalid, field, value);
+        }
+
+        if !(self.multipoint_prob + self.multipoint_avg_prob + self.singlepoint_prob > 0.0) {
+            invalid.push(validation::InvalidSetting::new("multipoint_prob", format!(
+                "the crossover probabilities sum to {}, so no crossover operator can be chosen",
+                self.multipoint_prob + self.multipoint_avg_prob + self.singlepoint_prob)));
+        }
+
+        if !(self.change_link_weights_power > 0.0 && self.change_link_weights_power.is_finite()) {
+            invalid.push(validation::InvalidSetting::new("change_link_weights_power", format!(
+                "{} cannot scale weight changes, it must be positive", self.change_link_weights_power)));
+        }
+
+        if !(self.min_weight.is_finite() && self.max_weight.is_finite() && self.min_weight < self.max_weight) {
+            invalid.push(validation::InvalidSetting::new("min_weight", format!(
+                "[{},{}] is not a range of weights, min_weight must be below max_weight",
+                self.min_weight, self.max_weight)));
+        }
+
+        if self.activation_functions.to_vec().is_empty() {
+            invalid.push(validation::InvalidSetting::new("activation_functions",
+                "no activation function is enabled, so new nodes cannot be created".to_string()));
+        }
+
+        invalid
+    }
+}
+
 /// We keep track of new link / new node mutations that happen in a generation as 'innovations'.
 /// Roughly, we wish to give genes that are created due to the same mutation the same innovation number.
 /// The innovation numbers are then used during crossover to determine matching genes.
@@ -93,7 +236,8 @@ pub fn mutate<R: rand::Rng>(genome: &mut genes::Genome,
         //println!("NEW NODE");
         new_node(genome, rng, &mut state.node_innovations,
                               &mut state.innovation_counter,
-                              &mut state.node_counter);
+                              &mut state.node_counter,
+                              &settings.activation_functions);
     } else if rng.next_f64() < settings.new_link_prob {
         //println!("NEW LINK");
         new_link(genome, rng,
@@ -101,14 +245,30 @@ pub fn mutate<R: rand::Rng>(genome: &mut genes::Genome,
                  &mut state.innovation_counter,
                  settings.recurrent_link_prob,
                  settings.self_link_prob, 30);
+    } else if settings.delete_link_prob > 0.0 && rng.next_f64() < settings.delete_link_prob {
+        delete_link(genome, rng, 30);
+    } else if settings.delete_node_prob > 0.0 && rng.next_f64() < settings.delete_node_prob {
+        delete_node(genome, rng, 30);
     } else {
         if rng.next_f64() < settings.toggle_enable_prob {
             toggle_enable(genome, rng);
         }
 
+        if rng.next_f64() < settings.change_activation_prob {
+            change_activation(genome, rng, &settings.activation_functions);
+        }
+
         if rng.next_f64() < settings.change_link_weights_prob {
-            //change_link_weights_standard(genome, rng, 1.0, settings.change_link_weights_power);
-            change_link_weights_perturbate_some(genome, rng, 0.3, settings.change_link_weights_power);
+            let change = settings.weight_change();
+
+            match settings.weight_mutation {
+                WeightMutation::Standard =>
+                    change_link_weights_standard(genome, rng, settings.weight_mutation_rate, &change),
+                WeightMutation::PerturbateSome =>
+                    change_link_weights_perturbate_some(genome, rng, settings.weight_mutation_rate, &change),
+                WeightMutation::ResetAll =>
+                    change_link_weights_reset_all(genome, rng, &change),
+            }
         }
     }
 }
@@ -147,16 +307,152 @@ pub fn toggle_enable<R: rand::Rng>(genome: &mut genes::Genome, rng: &mut R) {
     }
 }
 
+/// Checks if the node can be reached from an input over enabled links,
+/// without using the link `skip_link` or passing the node `skip_node`.
+/// As in `Genome::validate`, a path from the bias alone does not count.
+fn is_reachable_without(genome: &genes::Genome,
+                        id: genes::NodeId,
+                        skip_link: Option<(genes::NodeId, genes::NodeId)>,
+                        skip_node: Option<genes::NodeId>) -> bool {
+    let mut visited = HashSet::new();
+    let mut stack = vec![id];
+
+    // Search backwards from the node
+    while let Some(node_id) = stack.pop() {
+        if !visited.insert(node_id) {
+            continue;
+        }
+
+        match genome.get_node(node_id).unwrap().node_type {
+            genes::NodeType::Input => return true,
+            _ => ()
+        }
+
+        for link in genome.predecessor_links(node_id) {
+            if Some((link.from_id, link.to_id)) != skip_link && Some(link.from_id) != skip_node {
+                stack.push(link.from_id);
+            }
+        }
+    }
+
+    false
+}
+
+/// Ids of the other nodes that share a link gene with the node, enabled or not
+fn neighbour_ids(genome: &genes::Genome, id: genes::NodeId) -> Vec<genes::NodeId> {
+    genome.links.iter()
+                .filter(|link| (link.from_id == id) != (link.to_id == id))
+                .map(|link| if link.from_id == id { link.to_id } else { link.from_id })
+                .collect()
+}
+
+/// Removes the hidden nodes among `candidates` that have no link gene in or no link gene out,
+/// such as the ends of a removed link. Disabled links count, since they can be enabled again.
+/// The neighbours of a removed node become candidates themselves.
+fn remove_orphaned_nodes(genome: &mut genes::Genome, mut candidates: Vec<genes::NodeId>) {
+    while let Some(id) = candidates.pop() {
+        match genome.get_node(id) {
+            Some(node) if node.node_type == genes::NodeType::Hidden => (),
+            _ => continue
+        }
+
+        // Self-links neither lead into nor out of the node
+        let has_link_in = genome.links.iter().any(|link| link.to_id == id && link.from_id != id);
+        let has_link_out = genome.links.iter().any(|link| link.from_id == id && link.to_id != id);
+
+        if !(has_link_in && has_link_out) {
+            candidates.extend(neighbour_ids(genome, id));
+            genome.remove_node(id);
+        }
+    }
+}
+
+/// Removes a random link gene, and the hidden nodes that are left without links in or out.
+/// An enabled link is only removed if its target stays reachable from the inputs,
+/// so that no output is cut off.
+pub fn delete_link<R: rand::Rng>(genome: &mut genes::Genome, rng: &mut R, tries: usize) {
+    if genome.links.is_empty() {
+        return;
+    }
+
+    for _ in 0..tries {
+        let link = genome.links[rng.gen_range(0, genome.links.len())];
+
+        if !link.enabled || is_reachable_without(genome, link.to_id, Some((link.from_id, link.to_id)), None) {
+            genome.remove_link(link.from_id, link.to_id);
+            remove_orphaned_nodes(genome, vec![link.from_id, link.to_id]);
+            return;
+        }
+    }
+}
+
+/// Removes a random hidden node with all its links, and the hidden nodes that are left without links in or out.
+/// A node is only removed if the nodes it leads to stay reachable from the inputs,
+/// so that no output is cut off.
+pub fn delete_node<R: rand::Rng>(genome: &mut genes::Genome, rng: &mut R, tries: usize) {
+    let hidden_ids = genome.nodes.iter()
+                                 .filter(|node| node.node_type == genes::NodeType::Hidden)
+                                 .map(|node| node.id)
+                                 .collect::<Vec<_>>();
+
+    if hidden_ids.is_empty() {
+        return;
+    }
+
+    for _ in 0..tries {
+        let id = *rng.choose(&hidden_ids).unwrap();
+
+        if genome.successor_links(id).iter()
+                 .filter(|link| link.to_id != id)
+                 .all(|link| is_reachable_without(genome, link.to_id, None, Some(id))) {
+            let neighbour_ids = neighbour_ids(genome, id);
+            genome.remove_node(id);
+            remove_orphaned_nodes(genome, neighbour_ids);
+            return;
+        }
+    }
+}
+
+/// Switches the activation function of a random hidden node to another one of the allowed functions
+pub fn change_activation<R: rand::Rng>(genome: &mut genes::Genome,
+                                       rng: &mut R,
+                                       activation_functions: &genes::ActivationFunctionSet) {
+    let hidden_node_indices =
+        genome.nodes.iter().enumerate()
+              .filter(|&(_, node)| node.node_type == genes::NodeType::Hidden)
+              .map(|(i, _)| i)
+              .collect::<Vec<usize>>();
+
+    match rng.choose(&hidden_node_indices) {
+        Some(index) => {
+            let node = &mut genome.nodes[*index];
+
+            let choices = activation_functions.to_vec().into_iter()
+                                              .filter(|function| *function != node.activation)
+                                              .collect::<Vec<_>>();
+
+            match rng.choose(&choices) {
+                Some(function) => node.activation = *function,
+                None => ()
+            }
+        },
+
+        None => ()
+    }
+}
+
 /// Reenable the first gene we can find
 
 
 /// Add a new node to the genome by inserting it in the middle of an existing link between two nodes.
 /// This function takes a set of node innovations that happened in this generation so far as a parameter.
+/// Panics if no activation function is allowed, which `Settings::validate` rejects.
 pub fn new_node<R: rand::Rng>(genome: &mut genes::Genome,
                               rng: &mut R,
                               innovations: &mut NewNodeInnovations,
                               innovation_counter: &mut usize,
-                              node_counter: &mut usize) {
+                              node_counter: &mut usize,
+                              activation_functions: &genes::ActivationFunctionSet) {
     // Select a link gene to split up. The link must not be in a disabled state. 
     let enabled_gene_indices = 
         genome.links.iter().enumerate()
@@ -164,6 +460,9 @@ pub fn new_node<R: rand::Rng>(genome: &mut genes::Genome,
               .map(|(i, _)| i)
               .collect::<Vec<usize>>();
     
+    let activation = *rng.choose(&activation_functions.to_vec())
+                         .expect("No activation functions allowed for hidden nodes");
+
     match rng.choose(&enabled_gene_indices) {
         Some(index) => {
             let (link1, link2, node) = {
@@ -210,12 +509,13 @@ pub fn new_node<R: rand::Rng>(genome: &mut genes::Genome,
                                           weight: link.weight,
                                           is_recurrent: false }; // ???
                 let node = genes::Node { id: new_node_id,
-                                         node_type: genes::NodeType::Hidden };
+                                         node_type: genes::NodeType::Hidden,
+                                         activation: activation };
 
                 (link1, link2, node)
             };
 
-            genome.nodes.push(node);
+            genome.add_node(node);
             genome.add_link(link1);
             genome.add_link(link2);
         }
@@ -326,23 +626,46 @@ pub enum LinkMutation {
     None
 }
 
+/// The size and range of link weight changes
+#[derive(Clone, Copy, Debug)]
+pub struct WeightChange {
+    pub power: f64,
+    pub distribution: WeightDistribution,
+    pub min_weight: f64,
+    pub max_weight: f64,
+}
+
+impl WeightChange {
+    fn sample<R: rand::Rng>(&self, rng: &mut R) -> f64 {
+        match self.distribution {
+            WeightDistribution::Uniform =>
+                rand_pos_neg(rng) * rng.next_f64() * self.power,
+            WeightDistribution::Gaussian =>
+                Normal::new(0.0, self.power).ind_sample(rng),
+            WeightDistribution::Cauchy =>
+                (f64::consts::PI * (rng.next_f64() - 0.5)).tan() * self.power,
+        }
+    }
+}
+
 /// Apply a link weight mutation to each gene in the genome.
 /// F chooses for each link gene a mutation to be applied (depending on the position in the genome):
-/// * Perturb adds a random value in `(-power,power)` to the link weight.
-/// * Reset sets the link weight to a random value in `(-power,power)`.
+/// * Perturb adds a random value drawn from the distribution of `change` to the link weight.
+/// * Reset sets the link weight to a random value drawn from the distribution.
 /// * None leaves the link weight unmodified.
-pub fn change_link_weights<R: rand::Rng, F: FnMut(&mut R, usize) -> LinkMutation>(genome: &mut genes::Genome, rng: &mut R, mut f: F, power: f64) {
+/// Weights are kept within the bounds of `change`.
+pub fn change_link_weights<R: rand::Rng, F: FnMut(&mut R, usize) -> LinkMutation>(genome: &mut genes::Genome, rng: &mut R, mut f: F, change: &WeightChange) {
     for (position, link) in genome.links.iter_mut().enumerate() {
         match f(rng, position) {
             LinkMutation::Perturbate =>
-                link.weight += rand_pos_neg(rng) * rng.next_f64() * power,
+                link.weight += change.sample(rng),
             LinkMutation::Reset =>
-                link.weight = rand_pos_neg(rng) * rng.next_f64() * power,
+                link.weight = change.sample(rng),
             LinkMutation::None => (),
         }
 
-        if link.weight > 8.0 { link.weight = 8.0; }
-        if link.weight < -8.0 { link.weight = -8.0; }
+        if link.weight > change.max_weight { link.weight = change.max_weight; }
+        if link.weight < change.min_weight { link.weight = change.min_weight; }
     }
 }
 
@@ -358,7 +681,7 @@ fn rand_link_mutation<R: rand::Rng>(rng: &mut R, perturbate_point: f64, reset_po
     }
 }
 
-pub fn change_link_weights_standard<R: rand::Rng>(genome: &mut genes::Genome, rng: &mut R, rate: f64, power: f64) {
+pub fn change_link_weights_standard<R: rand::Rng>(genome: &mut genes::Genome, rng: &mut R, rate: f64, change: &WeightChange) {
     let severe = rng.gen::<bool>();
     let num_links = genome.links.len();
 
@@ -382,13 +705,286 @@ pub fn change_link_weights_standard<R: rand::Rng>(genome: &mut genes::Genome, rn
         }
     };
 
-    change_link_weights(genome, rng, f, power);
+    change_link_weights(genome, rng, f, change);
 }
 
-pub fn change_link_weights_perturbate_some<R: rand::Rng>(genome: &mut genes::Genome, rng: &mut R, prob: f64, power: f64) {
-    change_link_weights(genome, rng, |rng, _| if rng.next_f64() < prob { LinkMutation::Perturbate } else { LinkMutation::None }, power);
+pub fn change_link_weights_perturbate_some<R: rand::Rng>(genome: &mut genes::Genome, rng: &mut R, prob: f64, change: &WeightChange) {
+    change_link_weights(genome, rng, |rng, _| if rng.next_f64() < prob { LinkMutation::Perturbate } else { LinkMutation::None }, change);
 }
 
-pub fn change_link_weights_reset_all<R: rand::Rng>(genome: &mut genes::Genome, rng: &mut R, power: f64) {
-    change_link_weights(genome, rng, |_, _| LinkMutation::Reset, power);
+pub fn change_link_weights_reset_all<R: rand::Rng>(genome: &mut genes::Genome, rng: &mut R, change: &WeightChange) {
+    change_link_weights(genome, rng, |_, _| LinkMutation::Reset, change);
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{SeedableRng, StdRng};
+
+    use genes;
+    use genes::fixtures::{self, link};
+    use super::*;
+
+    fn rng(seed: usize) -> StdRng {
+        SeedableRng::from_seed(&[seed][..])
+    }
+
+    /// Inputs 0 and 1, bias 2, output 3 and the given hidden nodes and links
+    fn genome(hidden_ids: &[genes::NodeId], links: &[genes::Link]) -> genes::Genome {
+        fixtures::genome(hidden_ids.iter().map(|&id| fixtures::node(id, genes::NodeType::Hidden)).collect(),
+                         links.to_vec())
+    }
+
+    fn node_ids(genome: &genes::Genome) -> Vec<genes::NodeId> {
+        genome.nodes.iter().map(|node| node.id).collect()
+    }
+
+    #[test]
+    fn delete_link_keeps_only_path() {
+        let original = genome(&[4], &[link(0, 4, 0), link(4, 3, 1)]);
+        let mut genome = original.clone();
+
+        delete_link(&mut genome, &mut rng(1), 30);
+
+        assert_eq!(genome.links, original.links);
+    }
+
+    #[test]
+    fn delete_link_keeps_outputs_reachable() {
+        let original = genome(&[4], &[link(0, 4, 0), link(4, 3, 1), link(1, 3, 2)]);
+
+        for seed in 0..20 {
+            let mut genome = original.clone();
+            delete_link(&mut genome, &mut rng(seed), 30);
+
+            assert!(genome.validate().is_empty(), "{:?}", genome);
+
+            // Either the direct link goes, or the path through the hidden node together with the node
+            if genome.is_node(4) {
+                assert_eq!(genome.links, vec![link(0, 4, 0), link(4, 3, 1)]);
+            } else {
+                assert_eq!(genome.links, vec![link(1, 3, 2)]);
+            }
+        }
+    }
+
+    #[test]
+    fn delete_link_ignores_paths_from_bias() {
+        let original = genome(&[], &[link(0, 3, 0), link(2, 3, 1)]);
+
+        for seed in 0..20 {
+            let mut genome = original.clone();
+            delete_link(&mut genome, &mut rng(seed), 30);
+
+            assert!(genome.links.contains(&link(0, 3, 0)));
+            assert!(genome.validate().is_empty(), "{:?}", genome);
+        }
+    }
+
+    #[test]
+    fn delete_node_ignores_paths_from_bias() {
+        let original = genome(&[4], &[link(0, 4, 0), link(4, 3, 1), link(2, 3, 2)]);
+
+        for seed in 0..20 {
+            let mut genome = original.clone();
+            delete_node(&mut genome, &mut rng(seed), 30);
+
+            assert!(genome.is_node(4));
+            assert!(genome.validate().is_empty(), "{:?}", genome);
+        }
+    }
+
+    #[test]
+    fn delete_disabled_link() {
+        let mut genome = genome(&[], &[genes::Link { enabled: false, .. link(0, 3, 0) }]);
+
+        delete_link(&mut genome, &mut rng(1), 30);
+
+        assert!(genome.links.is_empty());
+    }
+
+    #[test]
+    fn delete_node_keeps_only_path() {
+        let original = genome(&[4], &[link(0, 4, 0), link(4, 3, 1)]);
+        let mut genome = original.clone();
+
+        delete_node(&mut genome, &mut rng(1), 30);
+
+        assert_eq!(node_ids(&genome), node_ids(&original));
+        assert_eq!(genome.links, original.links);
+    }
+
+    #[test]
+    fn delete_node_with_links() {
+        let mut genome = genome(&[4], &[link(0, 4, 0), link(4, 4, 1), link(4, 3, 2), link(1, 3, 3)]);
+
+        delete_node(&mut genome, &mut rng(1), 30);
+
+        assert_eq!(node_ids(&genome), vec![0, 1, 2, 3]);
+        assert_eq!(genome.links, vec![link(1, 3, 3)]);
+        assert!(genome.validate().is_empty());
+    }
+
+    #[test]
+    fn remove_orphaned_nodes_without_links_in_or_out() {
+        let mut genome = genome(&[4, 5, 6, 7],
+                                &[link(0, 4, 0), link(4, 3, 1),
+                                  link(0, 5, 2), link(6, 3, 3), link(6, 6, 4), // Were connected by a removed link
+                                  genes::Link { enabled: false, .. link(1, 7, 5) }, link(7, 3, 6)]);
+
+        remove_orphaned_nodes(&mut genome, vec![5, 6, 7]);
+
+        assert_eq!(node_ids(&genome), vec![0, 1, 2, 3, 4, 7]);
+        assert_eq!(genome.links, vec![link(0, 4, 0), link(4, 3, 1),
+                                      genes::Link { enabled: false, .. link(1, 7, 5) }, link(7, 3, 6)]);
+    }
+
+    #[test]
+    fn remove_orphaned_nodes_follows_chain() {
+        let mut genome = genome(&[4, 5], &[link(0, 4, 0), link(4, 5, 1), link(1, 3, 2)]);
+
+        remove_orphaned_nodes(&mut genome, vec![5]);
+
+        assert_eq!(node_ids(&genome), vec![0, 1, 2, 3]);
+        assert_eq!(genome.links, vec![link(1, 3, 2)]);
+    }
+
+    #[test]
+    fn node_behind_disabled_link_survives_unrelated_deletion() {
+        let disabled = genes::Link { enabled: false, .. link(1, 7, 3) };
+        let original = genome(&[4, 7], &[link(0, 4, 0), link(4, 3, 1), link(1, 3, 2), disabled, link(7, 3, 4)]);
+        let mut num_unrelated = 0;
+
+        for seed in 0..30 {
+            let mut genome = original.clone();
+            delete_link(&mut genome, &mut rng(seed), 30);
+
+            if !genome.links.contains(&link(1, 3, 2)) {
+                num_unrelated += 1;
+
+                assert!(genome.is_node(7));
+                assert_eq!(genome.links, vec![link(0, 4, 0), link(4, 3, 1), disabled, link(7, 3, 4)]);
+            }
+        }
+
+        assert!(num_unrelated > 0);
+    }
+
+    fn weight_change(distribution: WeightDistribution, power: f64) -> WeightChange {
+        WeightChange {
+            power: power,
+            distribution: distribution,
+            min_weight: -2.0,
+            max_weight: 3.0,
+        }
+    }
+
+    #[test]
+    fn changed_weights_stay_in_bounds() {
+        for &distribution in [WeightDistribution::Uniform, WeightDistribution::Gaussian, WeightDistribution::Cauchy].iter() {
+            let change = weight_change(distribution, 5.0);
+            let mut genome = genome(&[4], &[link(0, 4, 0), link(4, 3, 1), link(1, 3, 2), link(2, 3, 3)]);
+            let mut rng = rng(3);
+            let mut num_at_bound = 0;
+
+            for round in 0..200 {
+                change_link_weights(&mut genome, &mut rng, |_, _| {
+                    if round % 2 == 0 { LinkMutation::Perturbate } else { LinkMutation::Reset }
+                }, &change);
+
+                for link in genome.links.iter() {
+                    assert!(link.weight >= -2.0 && link.weight <= 3.0, "{:?} gave {}", distribution, link.weight);
+                    if link.weight == -2.0 || link.weight == 3.0 {
+                        num_at_bound += 1;
+                    }
+                }
+            }
+
+            // Changes this large must have been clamped
+            assert!(num_at_bound > 0, "{:?}", distribution);
+        }
+    }
+
+    /// Fraction of 10000 samples with an absolute value of at most `power`
+    fn fraction_within_power(distribution: WeightDistribution) -> f64 {
+        let change = weight_change(distribution, 0.5);
+        let mut rng = rng(4);
+
+        (0..10000).filter(|_| change.sample(&mut rng).abs() <= 0.5).count() as f64 / 10000.0
+    }
+
+    #[test]
+    fn weights_are_sampled_from_distribution() {
+        // All of the uniform distribution, one standard deviation of the Gaussian,
+        // and the quartiles of the Cauchy distribution
+        assert_eq!(fraction_within_power(WeightDistribution::Uniform), 1.0);
+        assert!((fraction_within_power(WeightDistribution::Gaussian) - 0.683).abs() < 0.02);
+        assert!((fraction_within_power(WeightDistribution::Cauchy) - 0.5).abs() < 0.02);
+    }
+
+    fn activation_functions(functions: &[genes::ActivationFunction]) -> genes::ActivationFunctionSet {
+        genes::ActivationFunctionSet {
+            tanh: functions.contains(&genes::ActivationFunction::Tanh),
+            steepened_sigmoid: functions.contains(&genes::ActivationFunction::SteepenedSigmoid),
+            relu: functions.contains(&genes::ActivationFunction::ReLU),
+            identity: functions.contains(&genes::ActivationFunction::Identity),
+            step: functions.contains(&genes::ActivationFunction::Step),
+            gaussian: functions.contains(&genes::ActivationFunction::Gaussian),
+            sine: functions.contains(&genes::ActivationFunction::Sine),
+            abs: functions.contains(&genes::ActivationFunction::Abs),
+        }
+    }
+
+    fn hidden_activations(genome: &genes::Genome) -> HashSet<genes::ActivationFunction> {
+        genome.nodes.iter()
+                    .filter(|node| node.node_type == genes::NodeType::Hidden)
+                    .map(|node| node.activation)
+                    .collect()
+    }
+
+    #[test]
+    fn new_nodes_use_allowed_activation_functions() {
+        let allowed = [genes::ActivationFunction::Gaussian, genes::ActivationFunction::Sine];
+        let mut genome = genome(&[], &[link(0, 3, 0), link(1, 3, 1)]);
+        let mut rng = rng(5);
+        let mut innovation_counter = 2;
+        let mut node_counter = 4;
+
+        for _ in 0..20 {
+            new_node(&mut genome, &mut rng, &mut HashMap::new(), &mut innovation_counter, &mut node_counter,
+                     &activation_functions(&allowed));
+        }
+
+        assert_eq!(genome.nodes.len(), 24);
+        assert_eq!(hidden_activations(&genome), allowed.iter().cloned().collect());
+    }
+
+    #[test]
+    fn changed_activations_use_allowed_functions() {
+        let allowed = [genes::ActivationFunction::ReLU, genes::ActivationFunction::Tanh];
+        let original = genome(&[4], &[link(0, 4, 0), link(4, 3, 1)]);
+
+        for seed in 0..10 {
+            let mut genome = original.clone();
+            change_activation(&mut genome, &mut rng(seed), &activation_functions(&allowed));
+
+            // The only other allowed function
+            assert_eq!(genome.get_node(4).unwrap().activation, genes::ActivationFunction::ReLU);
+        }
+
+        // Without another allowed function, the node keeps its function
+        let mut genome = original.clone();
+        change_activation(&mut genome, &mut rng(1), &activation_functions(&[genes::ActivationFunction::Tanh]));
+        assert_eq!(genome.get_node(4).unwrap().activation, genes::ActivationFunction::Tanh);
+    }
+
+    #[test]
+    fn no_activation_functions_is_invalid() {
+        let settings = Settings {
+            activation_functions: activation_functions(&[]),
+            .. STANDARD_SETTINGS
+        };
+
+        let fields = settings.validate().into_iter().map(|setting| setting.field).collect::<Vec<_>>();
+        assert_eq!(fields, vec!["activation_functions".to_string()]);
+    }
 }
@@ -2,12 +2,28 @@ use std::collections::HashMap;
 use genes;
 
 pub fn sigmoid(input_sum: f64) -> f64 {
-    //let slope = 4.924273;
-    //(1.0 / (1.0 + (-slope*input_sum).exp())) * 2.0 - 1.0
-
     input_sum.tanh()
 }
 
+/// The sigmoid of the original NEAT implementation, scaled to the range (-1,1)
+pub fn steepened_sigmoid(input_sum: f64) -> f64 {
+    let slope = 4.924273;
+    (1.0 / (1.0 + (-slope*input_sum).exp())) * 2.0 - 1.0
+}
+
+pub fn apply_activation(function: genes::ActivationFunction, input_sum: f64) -> f64 {
+    match function {
+        genes::ActivationFunction::Tanh => sigmoid(input_sum),
+        genes::ActivationFunction::SteepenedSigmoid => steepened_sigmoid(input_sum),
+        genes::ActivationFunction::ReLU => input_sum.max(0.0),
+        genes::ActivationFunction::Identity => input_sum,
+        genes::ActivationFunction::Step => if input_sum > 0.0 { 1.0 } else { 0.0 },
+        genes::ActivationFunction::Gaussian => (-input_sum.powi(2)).exp(),
+        genes::ActivationFunction::Sine => input_sum.sin(),
+        genes::ActivationFunction::Abs => input_sum.abs(),
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Node {
         gene: genes::Node,
@@ -18,27 +34,49 @@ pub struct Node {
         node_type: genes::NodeType,
 
     depth: Option<usize>,
+}
 
+/// The part of a node that changes while activating the network
+#[derive(Debug, Clone)]
+struct NodeState {
     active: bool,
     input_sum: f64,
     activation: f64,
 }
 
+/// Activation state of a network's nodes. The network itself is immutable, so it can be shared
+/// between threads and activated with as many states at once as needed.
+#[derive(Debug, Clone)]
+pub struct State {
+    nodes: Vec<NodeState>,
+}
+
 #[derive(Debug, Clone)]
 pub struct Network {
     id_to_index: Box<HashMap<genes::NodeId, usize>>,
     nodes: Vec<Node>, 
     max_depth: usize, // Length of the longest path contained in the network
+
+    // Node indices in topological order. None if the network contains a cycle.
+    feedforward_order: Option<Vec<usize>>,
 }
 
 impl Network {
-    pub fn from_genome(genome: &genes::Genome) -> Network {
+    /// Fails with the fatal problems of the genome, see `genes::Genome::validate`
+    pub fn from_genome(genome: &genes::Genome) -> Result<Network, Vec<genes::Problem>> {
+        let problems = genome.validate().into_iter()
+                                        .filter(|problem| problem.is_fatal())
+                                        .collect::<Vec<_>>();
+
+        if !problems.is_empty() {
+            return Err(problems);
+        }
+
         let (id_to_index, nodes) = {
             let id_to_index = {
                 let mut map = HashMap::new();
 
                 for (index, node) in genome.nodes.iter().enumerate() {
-                    assert!(!map.contains_key(&node.id), "Non-unique node ID in genome");
                     map.insert(node.id, index);
                 }
 
@@ -59,9 +97,6 @@ impl Network {
                                    .collect(),
                     node_type: node.node_type,
                     depth: None,
-                    active: false,
-                    input_sum: 0.0,
-                    activation: if node.node_type == genes::NodeType::Bias { 1.0 } else { 0.0 },
                 });
 
             (id_to_index.clone(), nodes.collect::<Vec<Node>>())
@@ -89,82 +124,126 @@ impl Network {
             id_to_index: id_to_index,
             nodes: nodes,
             max_depth: 0,
+            feedforward_order: None,
         };
 
         network.calc_depths();
-        network
+        Ok(network)
     }
 
-    /// Maximl number of links from an input to an output node
+    /// Sorts the nodes topologically and calculates the maximal number of links from an input to each node.
+    /// If the network contains a cycle, there is no such order and the depths are left unset.
     fn calc_depths(&mut self) {
-        /*// Depth search
+        // Kahn's algorithm: repeatedly take a node all of whose predecessors have been taken already
+        let mut num_missing = self.nodes.iter()
+                                        .map(|node| node.predecessor_indices.len())
+                                        .collect::<Vec<usize>>();
+        let mut queue = (0..self.nodes.len()).filter(|&index| num_missing[index] == 0)
+                                             .collect::<Vec<usize>>();
+        let mut order = Vec::with_capacity(self.nodes.len());
+
+        while let Some(index) = queue.pop() {
+            order.push(index);
+
+            for &succ_index in self.nodes[index].successor_indices.iter() {
+                num_missing[succ_index] -= 1;
+
+                if num_missing[succ_index] == 0 {
+                    queue.push(succ_index);
+                }
+            }
+        }
 
-        let mut visited = BTreeSet::new();
-        let mut queue = self.nodes.iter().enumerate()
-                                         .filter(|(i, node)| node.node_type == genes::NodeType::Input)
-                                         .map(|(i, node)| (i, 0))
-                                         .collect::<Vec<Node>>();
+        if order.len() < self.nodes.len() {
+            // Some nodes are part of a cycle
+            return;
+        }
 
-        while let Some(node_index, depth) = queue.pop() {
-            visited.insert(node_index);
+        let mut max_depth = 0;
 
-            let node = &mut self.nodes[node_index];
-            node.depth = match node.depth {
-                Some(old_depth) => Some(cmp::min(old_depth, depth));
-                None => Some(depth);
-            };
+        for &index in order.iter() {
+            let depth = self.nodes[index].predecessor_indices.iter()
+                            .map(|&pred_index| self.nodes[pred_index].depth.unwrap() + 1)
+                            .max()
+                            .unwrap_or(0);
 
-            for link in self.successor_links(node_id) {
-                if visited.contains(&link.to_id) || link.is_recurrent {
-                    continue
-                }
+            self.nodes[index].depth = Some(depth);
 
-                queue.push(link.to_id);
+            if depth > max_depth {
+                max_depth = depth;
             }
         }
 
-        return false;*/
+        self.max_depth = max_depth;
+        self.feedforward_order = Some(order);
+    }
+
+    /// True if the network contains no cycles, so that `activate_feedforward` can be used
+    pub fn is_feedforward(&self) -> bool {
+        self.feedforward_order.is_some()
+    }
+
+    /// Maximal number of links from an input to an output node. Zero if the network is not feed-forward.
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
     }
 
     pub fn num_inputs(&self) -> usize {
         self.nodes.iter().filter(|node| node.node_type == genes::NodeType::Input).count()
     }
 
-    pub fn get_output(&self) -> Vec<(genes::NodeId, f64)> {
-        self.nodes.iter().filter(|node| node.node_type == genes::NodeType::Output)
-                         .map(|node| (node.gene.id, node.activation))
+    /// Creates a new state for activating the network, with all nodes inactive
+    pub fn new_state(&self) -> State {
+        let mut state = State {
+            nodes: self.nodes.iter().map(|_| NodeState { active: false, input_sum: 0.0, activation: 0.0 })
+                                    .collect()
+        };
+
+        self.flush(&mut state);
+        state
+    }
+
+    pub fn get_output(&self, state: &State) -> Vec<(genes::NodeId, f64)> {
+        self.nodes.iter().zip(state.nodes.iter())
+                         .filter(|&(node, _)| node.node_type == genes::NodeType::Output)
+                         .map(|(node, node_state)| (node.gene.id, node_state.activation))
                          .collect()
     }
 
-    pub fn are_outputs_activated(&self) -> bool {
-        self.nodes.iter().filter(|node| node.node_type == genes::NodeType::Output)
-                         .all(|node| node.active)
+    pub fn are_outputs_activated(&self, state: &State) -> bool {
+        self.nodes.iter().zip(state.nodes.iter())
+                         .filter(|&(node, _)| node.node_type == genes::NodeType::Output)
+                         .all(|(_, node_state)| node_state.active)
     }
 
-    pub fn set_input(&mut self, input: &Vec<(genes::NodeId, f64)>) {
+    pub fn set_input(&self, state: &mut State, input: &Vec<(genes::NodeId, f64)>) {
         for &(id, activation) in input.iter() {
-            self.nodes[*self.id_to_index.get(&id).unwrap()].activation = activation;
+            state.nodes[*self.id_to_index.get(&id).unwrap()].activation = activation;
         }
     }
 
     /// Resets the state of the network, setting all nodes to inactive
-    pub fn flush(&mut self) {
-        for node in self.nodes.iter_mut() {
-            node.active = false;
-            node.activation = if node.node_type == genes::NodeType::Bias { 1.0 } else { 0.0 };
+    pub fn flush(&self, state: &mut State) {
+        assert_eq!(state.nodes.len(), self.nodes.len());
+
+        for (node, node_state) in self.nodes.iter().zip(state.nodes.iter_mut()) {
+            node_state.active = false;
+            node_state.activation = if node.node_type == genes::NodeType::Bias { 1.0 } else { 0.0 };
         }
     }
 
-    pub fn activate(&mut self) {
-        for node in self.nodes.iter_mut() {
+    pub fn activate(&self, state: &mut State) {
+        assert_eq!(state.nodes.len(), self.nodes.len());
+
+        for (node, node_state) in self.nodes.iter().zip(state.nodes.iter()) {
             /*if node.node_type == genes::NodeType::Input {
-                println!("INPUT: {}, {}", node.gene.id, node.activation);
+                println!("INPUT: {}, {}", node.gene.id, node_state.activation);
             }
             if node.node_type == genes::NodeType::Bias {
-                println!("BIAS: {}, {}", node.gene.id, node.activation);
+                println!("BIAS: {}, {}", node.gene.id, node_state.activation);
             }*/
             if node.node_type == genes::NodeType::Bias {
-                assert_eq!(node.activation, 1.0);
+                assert_eq!(node_state.activation, 1.0);
             }
         }
 
@@ -186,45 +265,182 @@ impl Network {
                         .fold((false, 0.0),
                               |(active, input_sum), (in_index, weight)| {
                                   let in_node = &self.nodes[*in_index];
-                                  let in_active = in_node.active || in_node.node_type == genes::NodeType::Input
-                                                                 || in_node.node_type == genes::NodeType::Bias;
+                                  let in_state = &state.nodes[*in_index];
+                                  let in_active = in_state.active || in_node.node_type == genes::NodeType::Input
+                                                                  || in_node.node_type == genes::NodeType::Bias;
 
                                   if in_active {
-                                      //println!("{} gets {} * {} from {}", node.gene.id, weight, in_node.activation, in_node.gene.id);
+                                      //println!("{} gets {} * {} from {}", node.gene.id, weight, in_state.activation, in_node.gene.id);
                                   }
 
                                   (active || in_active,
-                                   if in_active { input_sum + weight * in_node.activation }
+                                   if in_active { input_sum + weight * in_state.activation }
                                    else { input_sum })
                               })
                 };
 
 
                 // Update state in array
-                self.nodes[node_index].active = active;
-                self.nodes[node_index].input_sum = input_sum;
+                state.nodes[node_index].active = active;
+                state.nodes[node_index].input_sum = input_sum;
             }
 
             // Calculate activation of each node based on the input we just calculated
-            for node in self.nodes.iter_mut() {
+            for (node, node_state) in self.nodes.iter().zip(state.nodes.iter_mut()) {
                 if node.node_type == genes::NodeType::Input ||
                    node.node_type == genes::NodeType::Bias {
                     continue;
                 }
 
-                if node.active {
-                    node.activation = sigmoid(node.input_sum);
-                    //println!("activate {} with {} -> {}", node.gene.id, node.input_sum, node.activation);
+                if node_state.active {
+                    node_state.activation = apply_activation(node.gene.activation, node_state.input_sum);
+                    //println!("activate {} with {} -> {}", node.gene.id, node_state.input_sum, node_state.activation);
                 }
             }
 
-            /*if self.are_outputs_activated() {
+            /*if self.are_outputs_activated(state) {
                 break;
             }*/
         //}
 
-        if !self.are_outputs_activated() {
+        if !self.are_outputs_activated(state) {
             //println!("couldn't activate all outputs in time");
         }
     }
+
+    /// Activates each node exactly once, in topological order.
+    /// This gives the outputs that repeated calls to `activate` would eventually settle on.
+    /// Panics if the network is not feed-forward.
+    pub fn activate_feedforward(&self, state: &mut State) {
+        assert_eq!(state.nodes.len(), self.nodes.len());
+
+        let order = self.feedforward_order.as_ref().expect("Network contains a cycle");
+
+        for &node_index in order.iter() {
+            let node = &self.nodes[node_index];
+
+            if node.node_type == genes::NodeType::Input ||
+               node.node_type == genes::NodeType::Bias {
+                continue;
+            }
+
+            // All predecessors come before us in the order, so their activation is final
+            let (active, input_sum) =
+                node.predecessor_indices.iter()
+                    .zip(node.weights.iter())
+                    .fold((false, 0.0),
+                          |(active, input_sum), (in_index, weight)| {
+                              let in_node = &self.nodes[*in_index];
+                              let in_state = &state.nodes[*in_index];
+                              let in_active = in_state.active || in_node.node_type == genes::NodeType::Input
+                                                              || in_node.node_type == genes::NodeType::Bias;
+
+                              (active || in_active,
+                               if in_active { input_sum + weight * in_state.activation }
+                               else { input_sum })
+                          });
+
+            let node_state = &mut state.nodes[node_index];
+            node_state.active = active;
+            node_state.input_sum = input_sum;
+
+            if active {
+                node_state.activation = apply_activation(node.gene.activation, input_sum);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use genes;
+    use genes::fixtures;
+    use super::*;
+
+    /// Inputs 0 and 1, bias 2, output 3, and hidden nodes 4 and 5 listed after the output,
+    /// connected by the given links
+    fn network(links: &[(genes::NodeId, genes::NodeId, bool)]) -> Network {
+        let links = links.iter().enumerate().map(|(innovation, &(from_id, to_id, enabled))| genes::Link {
+            enabled: enabled,
+            weight: 0.5,
+            .. fixtures::link(from_id, to_id, innovation)
+        }).collect();
+        let mut genome = fixtures::genome(vec![fixtures::node(5, genes::NodeType::Hidden),
+                                               fixtures::node(4, genes::NodeType::Hidden)],
+                                          links);
+        for node in genome.nodes.iter_mut() {
+            node.activation = genes::ActivationFunction::Identity;
+        }
+
+        Network::from_genome(&genome).unwrap()
+    }
+
+    #[test]
+    fn topological_order_of_chain() {
+        let network = network(&[(5, 3, true), (4, 5, true), (0, 4, true), (1, 3, true)]);
+        let order = network.feedforward_order.clone().unwrap();
+        let position = |id| order.iter().position(|&index| network.nodes[index].gene.id == id).unwrap();
+
+        assert_eq!(order.len(), 6);
+        assert!(position(0) < position(4) && position(4) < position(5) && position(5) < position(3));
+        assert!(position(1) < position(3));
+        assert_eq!(network.max_depth(), 3);
+    }
+
+    #[test]
+    fn cycles_are_detected() {
+        assert!(!network(&[(0, 4, true), (4, 5, true), (5, 4, true), (5, 3, true)]).is_feedforward());
+        assert!(!network(&[(0, 4, true), (4, 4, true), (4, 3, true)]).is_feedforward());
+        assert!(!network(&[(0, 3, true), (3, 4, true), (4, 3, true)]).is_feedforward());
+        assert_eq!(network(&[(4, 4, true)]).max_depth(), 0);
+    }
+
+    #[test]
+    fn disabled_links_do_not_form_cycles() {
+        let network = network(&[(0, 4, true), (4, 5, true), (5, 4, false), (5, 3, true)]);
+
+        assert!(network.is_feedforward());
+        assert_eq!(network.max_depth(), 3);
+    }
+
+    #[test]
+    fn feedforward_activation_matches_repeated_activation() {
+        let network = network(&[(0, 4, true), (2, 4, true), (4, 5, true), (1, 5, true), (5, 3, true), (0, 3, true)]);
+        let input = vec![(0, 0.3), (1, -0.7)];
+
+        let mut feedforward_state = network.new_state();
+        network.set_input(&mut feedforward_state, &input);
+        network.activate_feedforward(&mut feedforward_state);
+
+        let mut state = network.new_state();
+        network.set_input(&mut state, &input);
+        for _ in 0..network.max_depth() + 1 {
+            network.activate(&mut state);
+        }
+
+        assert_eq!(network.get_output(&feedforward_state), network.get_output(&state));
+        assert!(network.are_outputs_activated(&feedforward_state));
+    }
+
+    #[test]
+    fn outputs_use_their_activation_function() {
+        let input = vec![(0, 0.3), (1, -0.7)];
+
+        for &activation in genes::ACTIVATION_FUNCTIONS.iter() {
+            let mut genome = genes::Genome::initial_genome(2, 1, 2, true);
+            genome.set_output_activation(activation);
+            for link in genome.links.iter_mut() {
+                link.weight = 0.5;
+            }
+
+            let network = Network::from_genome(&genome).unwrap();
+            let mut state = network.new_state();
+            network.set_input(&mut state, &input);
+            network.activate_feedforward(&mut state);
+
+            // The output gets 0.5 from the bias, 0.15 and -0.35 from the inputs
+            let expected = apply_activation(activation, 0.3);
+            assert!((network.get_output(&state)[0].1 - expected).abs() < 1e-12, "{:?}", activation);
+        }
+    }
 }
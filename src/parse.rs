@@ -0,0 +1,315 @@
+use std::char;
+use std::collections::BTreeMap;
+
+use rustc_serialize::json::Json;
+
+/// Parses JSON like `Json::from_str`, but reads floating point numbers exactly.
+/// The parser of rustc_serialize is off by the last bit for some numbers, e.g. 0.3,
+/// so numbers are read with `str::parse` instead, which rounds correctly.
+pub fn json(s: &str) -> Result<Json, String> {
+    let mut parser = JsonParser { s: s, pos: 0 };
+    let json = try!(parser.value());
+
+    parser.skip_whitespace();
+    if parser.pos < s.len() {
+        return Err(parser.error("trailing characters"));
+    }
+
+    Ok(json)
+}
+
+struct JsonParser<'a> {
+    s: &'a str,
+    pos: usize, // Byte offset of the next character
+}
+
+impl<'a> JsonParser<'a> {
+    fn error(&self, message: &str) -> String {
+        format!("{} at byte {}", message, self.pos)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.s.as_bytes().get(self.pos).cloned()
+    }
+
+    fn skip_whitespace(&mut self) {
+        loop {
+            match self.peek() {
+                Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') => self.pos += 1,
+                _ => break
+            }
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), String> {
+        if self.peek() == Some(byte) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", byte as char)))
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+
+        match self.peek() {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b't') => self.literal("true", Json::Boolean(true)),
+            Some(b'f') => self.literal("false", Json::Boolean(false)),
+            Some(b'n') => self.literal("null", Json::Null),
+            Some(b) if b == b'-' || (b as char).is_digit(10) => self.number(),
+            Some(_) => Err(self.error("expected value")),
+            None => Err(self.error("unexpected end"))
+        }
+    }
+
+    fn literal(&mut self, literal: &str, value: Json) -> Result<Json, String> {
+        if self.s[self.pos..].starts_with(literal) {
+            self.pos += literal.len();
+            Ok(value)
+        } else {
+            Err(self.error("invalid literal"))
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        let mut object = BTreeMap::new();
+
+        try!(self.expect(b'{'));
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Json::Object(object));
+        }
+
+        loop {
+            self.skip_whitespace();
+            let key = try!(self.string());
+
+            self.skip_whitespace();
+            try!(self.expect(b':'));
+            let value = try!(self.value());
+            object.insert(key, value);
+
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Json::Object(object));
+                },
+                _ => return Err(self.error("expected ',' or '}'"))
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        let mut array = vec![];
+
+        try!(self.expect(b'['));
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Json::Array(array));
+        }
+
+        loop {
+            array.push(try!(self.value()));
+
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Json::Array(array));
+                },
+                _ => return Err(self.error("expected ',' or ']'"))
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let mut string = String::new();
+
+        try!(self.expect(b'"'));
+
+        loop {
+            // Copy everything up to the next quote or escape. Both are ASCII, so the slice
+            // ends on a character boundary.
+            let start = self.pos;
+            while let Some(b) = self.peek() {
+                if b == b'"' || b == b'\\' {
+                    break;
+                }
+                self.pos += 1;
+            }
+            string.push_str(&self.s[start..self.pos]);
+
+            match self.peek() {
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(string);
+                },
+                Some(_) => {
+                    self.pos += 1;
+                    string.push(try!(self.escape()));
+                },
+                None => return Err(self.error("unterminated string"))
+            }
+        }
+    }
+
+    /// The character of the escape sequence after a backslash
+    fn escape(&mut self) -> Result<char, String> {
+        let c = match self.peek() {
+            Some(c) => c,
+            None => return Err(self.error("unterminated string"))
+        };
+        self.pos += 1;
+
+        match c {
+            b'"' => Ok('"'),
+            b'\\' => Ok('\\'),
+            b'/' => Ok('/'),
+            b'b' => Ok('\x08'),
+            b'f' => Ok('\x0c'),
+            b'n' => Ok('\n'),
+            b'r' => Ok('\r'),
+            b't' => Ok('\t'),
+            b'u' => {
+                let mut code = try!(self.hex_code());
+
+                // Characters outside the basic multilingual plane are written as a pair of surrogates
+                if code >= 0xD800 && code < 0xDC00 && self.s[self.pos..].starts_with("\\u") {
+                    self.pos += 2;
+                    let low = try!(self.hex_code());
+
+                    if low < 0xDC00 || low >= 0xE000 {
+                        return Err(self.error("invalid surrogate pair"));
+                    }
+                    code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                }
+
+                char::from_u32(code).ok_or_else(|| self.error("invalid unicode escape"))
+            },
+            _ => Err(self.error("invalid escape"))
+        }
+    }
+
+    /// The four hex digits of a unicode escape
+    fn hex_code(&mut self) -> Result<u32, String> {
+        let rest = &self.s.as_bytes()[self.pos..];
+
+        if rest.len() < 4 || !rest[..4].iter().all(|&b| (b as char).is_digit(16)) {
+            return Err(self.error("invalid unicode escape"));
+        }
+
+        let code = rest[..4].iter().fold(0, |code, &b| code * 16 + (b as char).to_digit(16).unwrap());
+        self.pos += 4;
+        Ok(code)
+    }
+
+    fn digits(&mut self) -> Result<(), String> {
+        let start = self.pos;
+
+        while self.peek().map(|b| (b as char).is_digit(10)).unwrap_or(false) {
+            self.pos += 1;
+        }
+
+        if self.pos == start {
+            Err(self.error("expected digit"))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.pos;
+        let mut is_float = false;
+
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        try!(self.digits());
+
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            try!(self.digits());
+            is_float = true;
+        }
+
+        if self.peek() == Some(b'e') || self.peek() == Some(b'E') {
+            self.pos += 1;
+            if self.peek() == Some(b'+') || self.peek() == Some(b'-') {
+                self.pos += 1;
+            }
+            try!(self.digits());
+            is_float = true;
+        }
+
+        let literal = &self.s[start..self.pos];
+
+        // Integers are kept as integers like rustc_serialize does, so that they decode into integer fields
+        if !is_float {
+            if let Ok(value) = literal.parse::<u64>() {
+                return Ok(Json::U64(value));
+            }
+            if let Ok(value) = literal.parse::<i64>() {
+                return Ok(Json::I64(value));
+            }
+        }
+
+        literal.parse::<f64>().map(Json::F64).map_err(|_| self.error("invalid number"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustc_serialize::json::Json;
+    use super::*;
+
+    #[test]
+    fn floats_are_exact() {
+        let values = [0.3, 0.1, -2.5e-7, 1.0 / 3.0, 8.123456789012345e100];
+
+        for &value in values.iter() {
+            assert_eq!(json(&format!("{:?}", value)).unwrap(), Json::F64(value));
+            assert_eq!(json(&format!("[{:e}]", value)).unwrap(), Json::Array(vec![Json::F64(value)]));
+            assert_eq!(json(&format!("{{\"b\": {:?}, \"a\": \"{:?}\"}}", value, value)).unwrap().find("b"),
+                       Some(&Json::F64(value)));
+        }
+    }
+
+    #[test]
+    fn numbers_in_strings_are_skipped() {
+        let parsed = json(r#"{"a\"1.5": "2.5", "b": [7, -1, 0.3]}"#).unwrap();
+
+        assert_eq!(parsed.find("a\"1.5"), Some(&Json::String("2.5".to_string())));
+        assert_eq!(parsed.find("b"), Some(&Json::Array(vec![Json::U64(7), Json::I64(-1), Json::F64(0.3)])));
+    }
+
+    #[test]
+    fn parses_like_rustc_serialize() {
+        let s = r#" {"s": "tab\t quote\" \u00e9 \ud83d\ude00 /\/", "n": null, "t": true, "f": false,
+                     "e": [], "o": {}, "i": [0, 12, -3, 1e2, 2.5E-1]} "#;
+
+        assert_eq!(json(s).unwrap(), Json::from_str(s).unwrap());
+    }
+
+    #[test]
+    fn invalid_json_is_rejected() {
+        assert!(json("").is_err());
+        assert!(json("{\"a\": 1").is_err());
+        assert!(json("[1, 2] 3").is_err());
+        assert!(json("{\"a\": }").is_err());
+        assert!(json("[1,]").is_err());
+        assert!(json("\"\\x\"").is_err());
+        assert!(json("\"\\u12\"").is_err());
+        assert!(json("-").is_err());
+        assert!(json("1.").is_err());
+        assert!(json("tru").is_err());
+    }
+}
@@ -1,31 +1,188 @@
 extern crate rand;
 
 use std::cmp::Ordering;
-use rand::Rng;
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use rustc_serialize::{Decodable, Decoder, Encodable, Encoder};
+use rustc_serialize::json::{self, ToJson, Json};
 use genes;
 use mutation;
 use nn;
 use mating;
+use stats;
+use validation;
+use parse;
+
+/// Version of the checkpoint file format written by `Population::save`
+pub const CHECKPOINT_VERSION: u64 = 1;
+
+/// Reasons why a population cannot be created or loaded from a checkpoint
+#[derive(Debug)]
+pub enum Error {
+    File(genes::Error),
+    Settings(Vec<validation::InvalidSetting>),
+    InitialGenome(Vec<genes::Problem>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::File(ref err) => write!(f, "{}", err),
+            Error::Settings(ref invalid) => {
+                try!(write!(f, "Invalid settings:"));
+
+                for setting in invalid.iter() {
+                    try!(write!(f, " {}.", setting));
+                }
+
+                Ok(())
+            },
+            Error::InitialGenome(ref problems) => {
+                try!(write!(f, "Malformed initial genome:"));
+
+                for problem in problems.iter() {
+                    try!(write!(f, " {}.", problem));
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::File(ref err) => err.description(),
+            Error::Settings(_) => "invalid settings",
+            Error::InitialGenome(_) => "malformed initial genome",
+        }
+    }
+}
+
+impl From<genes::Error> for Error {
+    fn from(err: genes::Error) -> Error {
+        Error::File(err)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::File(genes::Error::Io(err))
+    }
+}
 
-#[derive(Clone, Copy)]
+impl From<json::DecoderError> for Error {
+    fn from(err: json::DecoderError) -> Error {
+        Error::File(genes::Error::from(err))
+    }
+}
+
+#[derive(Clone, Copy, RustcEncodable, RustcDecodable)]
 pub struct Settings {
     pub survival_threshold: f64,
     pub compat_threshold: f64,
     pub dropoff_age: Option<usize>,
     pub target_num_species: Option<usize>,
+    pub phased_search: Option<PhasedSearch>,
+}
+
+/// Settings for alternating between phases that grow and prune the genomes, as in SharpNEAT.
+/// Complexity is the number of node and link genes of a genome.
+#[derive(Clone, Copy, PartialEq, Debug, RustcEncodable, RustcDecodable)]
+pub struct PhasedSearch {
+    pub complexity_threshold: f64, // Pruning starts when mean complexity grows this much over a complexifying phase
+    pub stall_generations: usize, // Pruning ends when mean complexity has not fallen for this many generations
+    pub delete_link_prob: mutation::Prob, // Replace the probabilities of additions while pruning
+    pub delete_node_prob: mutation::Prob,
+}
+
+/// Whether the genomes are currently allowed to grow
+#[derive(Clone, Copy, PartialEq, Debug, RustcEncodable, RustcDecodable)]
+pub enum Phase {
+    Complexifying,
+    Simplifying,
+}
+
+impl Phase {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Phase::Complexifying => "complexifying",
+            Phase::Simplifying => "simplifying",
+        }
+    }
+}
+
+// `Population::epoch` changes the compat threshold in steps of this size to reach `target_num_species`,
+// but not below the minimum
+const COMPAT_THRESHOLD_STEP: f64 = 0.3;
+const MIN_COMPAT_THRESHOLD: f64 = 0.3;
+
+impl Settings {
+    pub fn validate(&self) -> Vec<validation::InvalidSetting> {
+        let mut invalid = vec![];
+
+        if !(self.survival_threshold >= 0.0 && self.survival_threshold <= 1.0) {
+            invalid.push(validation::InvalidSetting::new("survival_threshold", format!(
+                "{} is not a fraction of the species, it must be in [0,1]", self.survival_threshold)));
+        }
+
+        if !(self.compat_threshold > 0.0 && self.compat_threshold.is_finite()) {
+            invalid.push(validation::InvalidSetting::new("compat_threshold", format!(
+                "{} would put every organism into its own species, it must be positive", self.compat_threshold)));
+        } else if self.target_num_species.is_some() && self.compat_threshold < MIN_COMPAT_THRESHOLD {
+            invalid.push(validation::InvalidSetting::new("compat_threshold", format!(
+                "{} is below {}, the minimum kept when adjusting to target_num_species",
+                self.compat_threshold, MIN_COMPAT_THRESHOLD)));
+        }
+
+        if self.dropoff_age == Some(0) {
+            invalid.push(validation::InvalidSetting::new("dropoff_age",
+                "0 would penalize every species in every generation, use null to disable".to_string()));
+        }
+
+        if self.target_num_species == Some(0) {
+            invalid.push(validation::InvalidSetting::new("target_num_species",
+                "there is always at least one species, use null to keep the compat threshold fixed".to_string()));
+        }
+
+        if let Some(phased_search) = self.phased_search {
+            if !(phased_search.complexity_threshold > 0.0 && phased_search.complexity_threshold.is_finite()) {
+                invalid.push(validation::InvalidSetting::new("phased_search.complexity_threshold", format!(
+                    "{} would start pruning right away, it must be positive", phased_search.complexity_threshold)));
+            }
+
+            if phased_search.stall_generations == 0 {
+                invalid.push(validation::InvalidSetting::new("phased_search.stall_generations",
+                    "0 would end pruning right away, it must be positive".to_string()));
+            }
+
+            validation::check_prob(&mut invalid, "phased_search.delete_link_prob", phased_search.delete_link_prob);
+            validation::check_prob(&mut invalid, "phased_search.delete_node_prob", phased_search.delete_node_prob);
+        }
+
+        invalid
+    }
 }
 
 pub static STANDARD_SETTINGS: Settings = Settings {
     survival_threshold: 0.2,
     compat_threshold: 0.9,
     dropoff_age: Some(40),
-    target_num_species: Some(30)
+    target_num_species: Some(30),
+    phased_search: None,
 };
 
 #[derive(Clone)]
 pub struct Organism {
     pub genome: genes::Genome,
-    pub network: nn::Network,
+    pub network: Arc<nn::Network>,
     pub fitness: f64,
 
     adj_fitness: f64,
@@ -33,19 +190,64 @@ pub struct Organism {
 }
 
 impl Organism {
-    pub fn new(genome: &genes::Genome) -> Organism {
-        genome.assert_integrity();
+    /// Fails with the fatal problems of the genome, see `genes::Genome::validate`
+    pub fn new(genome: &genes::Genome) -> Result<Organism, Vec<genes::Problem>> {
+        let network = try!(nn::Network::from_genome(genome));
 
-        Organism {
+        Ok(Organism {
             genome: genome.clone(),
-            network: nn::Network::from_genome(genome),
+            network: Arc::new(network),
             fitness: 0.0,
             adj_fitness: 0.0,
             expected_offspring: 0.0
+        })
+    }
+
+    /// Like `new`, but should the genome be malformed, the genes causing the problems are removed.
+    /// For genomes made by reproduction, which must not stop evolution.
+    /// Returns the problems that were repaired, or fails with the ones left after repairing.
+    fn new_repaired(mut genome: genes::Genome) -> Result<(Organism, Vec<genes::Problem>), Vec<genes::Problem>> {
+        match Organism::new(&genome) {
+            Ok(organism) => Ok((organism, vec![])),
+            Err(problems) => {
+                genome.repair();
+                Organism::new(&genome).map(|organism| (organism, problems))
+            }
         }
     }
 }
 
+// The network is not stored, it is compiled from the genome again when decoding
+impl Encodable for Organism {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_struct("Organism", 4, |s| {
+            try!(s.emit_struct_field("genome", 0, |s| self.genome.encode(s)));
+            try!(s.emit_struct_field("fitness", 1, |s| self.fitness.encode(s)));
+            try!(s.emit_struct_field("adj_fitness", 2, |s| self.adj_fitness.encode(s)));
+            s.emit_struct_field("expected_offspring", 3, |s| self.expected_offspring.encode(s))
+        })
+    }
+}
+
+impl Decodable for Organism {
+    fn decode<D: Decoder>(d: &mut D) -> Result<Organism, D::Error> {
+        d.read_struct("Organism", 4, |d| {
+            let genome: genes::Genome = try!(d.read_struct_field("genome", 0, Decodable::decode));
+
+            let mut organism = match Organism::new(&genome) {
+                Ok(organism) => organism,
+                Err(_) => return Err(d.error("malformed genome in organism"))
+            };
+            organism.fitness = try!(d.read_struct_field("fitness", 1, Decodable::decode));
+            organism.adj_fitness = try!(d.read_struct_field("adj_fitness", 2, Decodable::decode));
+            organism.expected_offspring = try!(d.read_struct_field("expected_offspring", 3, Decodable::decode));
+
+            Ok(organism)
+        })
+    }
+}
+
+#[derive(RustcEncodable, RustcDecodable)]
 pub struct Species {
     pub organisms: Vec<Organism>,
 
@@ -65,6 +267,7 @@ pub struct Species {
     best_offspring: usize,
 }
 
+#[derive(RustcEncodable, RustcDecodable)]
 pub struct Population {
     settings: Settings,
     mutation_settings: mutation::Settings,
@@ -79,6 +282,68 @@ pub struct Population {
     pub generation: usize,
     highest_fitness: f64, // Over all time
     time_since_last_improvement: usize, // Used to detect stagnation
+
+    // State of the phased search, if enabled in the settings
+    phase: Phase,
+    complexity_ceiling: f64, // Mean complexity at which pruning starts
+    lowest_complexity: f64, // In the current simplifying phase
+    time_since_complexity_fell: usize,
+}
+
+/// A checkpoint read by `Checkpoint::load`: the population and the state of the run around it
+pub struct Checkpoint {
+    pub population: Population,
+    pub seed: usize, // Master seed of the run
+    pub experiment_state: Json, // Given by `Experiment::save_state`
+}
+
+/// The number of genes, used by the phased search
+fn complexity(genome: &genes::Genome) -> f64 {
+    (genome.nodes.len() + genome.links.len()) as f64
+}
+
+/// Picks an elite organism from a species other than `species_id`.
+/// Returns `None` if there is no other species.
+fn choose_interspecies_mate<'a, R: rand::Rng>(rng: &mut R,
+                                              all_species: &'a [Species],
+                                              species_id: usize,
+                                              selection: mutation::MateSelection) -> Option<&'a Organism> {
+    let others = all_species.iter()
+                            .filter(|species| species.id != species_id && species.organisms.len() > 0)
+                            .collect::<Vec<_>>();
+
+    if others.is_empty() {
+        return None;
+    }
+
+    match selection {
+        mutation::MateSelection::Random => {
+            let species = others[rng.gen_range(0, others.len())];
+            Some(&species.organisms[rng.gen_range(0, species.organisms.len())])
+        },
+        mutation::MateSelection::FitnessWeighted => {
+            // Roulette wheel selection. Fitness is positive after `prepare_for_epoch`.
+            let candidates = others.iter().flat_map(|species| species.organisms.iter()).collect::<Vec<_>>();
+            let sum_fitness = candidates.iter().fold(0.0, |sum, organism| sum + organism.fitness);
+
+            // Without any fitness, every organism is as good as the others
+            if !(sum_fitness > 0.0) {
+                return Some(candidates[rng.gen_range(0, candidates.len())]);
+            }
+
+            let mut point = rng.next_f64() * sum_fitness;
+
+            for organism in candidates.iter() {
+                if point < organism.fitness {
+                    return Some(organism);
+                }
+                point -= organism.fitness;
+            }
+
+            // Rounding may leave us just past the last organism that has fitness
+            candidates.iter().rev().find(|organism| organism.fitness > 0.0).map(|organism| *organism)
+        }
+    }
 }
 
 impl Species {
@@ -107,17 +372,14 @@ impl Species {
 
     /// Calculate organisms' adjusted fitness by dividing by species size (fitness sharing).
     /// Then, the organisms of the species are sorted by their adjusted fitness.
-    pub fn prepare_for_epoch(&mut self, dropoff_age: Option<usize>) {
+    /// Returns true if the species was penalized for stagnating.
+    pub fn prepare_for_epoch(&mut self, dropoff_age: Option<usize>) -> bool {
         let num_organisms = self.organisms.len();
         assert!(num_organisms > 0);
 
         // Penalty for species that don't improve for a longer time
         let penalize = dropoff_age.is_some() && self.time_since_last_improvement > dropoff_age.unwrap();
 
-        if penalize {
-            println!("Penalizing species {}", self.id);
-        }
-
         for organism in self.organisms.iter_mut() {
             assert!(organism.fitness >= 0.0);
 
@@ -147,6 +409,36 @@ impl Species {
 
         self.best_genome = self.best_organism().genome.clone();
         self.best_offspring = 1; // By default, give at least 1 offspring to the best genome
+
+        penalize
+    }
+
+    /// The best and the mean fitness of the organisms, as given by the evaluation
+    fn fitness_stats(&self) -> (f64, f64) {
+        let best = self.organisms.iter().map(|o| o.fitness).fold(0.0, f64::max);
+        let mean = self.organisms.iter().map(|o| o.fitness).fold(0.0, |x,y| x+y) / self.organisms.len() as f64;
+
+        (best, mean)
+    }
+
+    /// Statistics of the species after `prepare_for_epoch`, with the fitness taken before it by `fitness_stats`
+    fn stats(&self, penalized: bool, (best_fitness, mean_fitness): (f64, f64)) -> stats::SpeciesStats {
+        let num_organisms = self.organisms.len() as f64;
+
+        stats::SpeciesStats {
+            id: self.id,
+            size: self.organisms.len(),
+            expected_offspring: self.expected_offspring,
+            best_fitness: best_fitness,
+            mean_fitness: mean_fitness,
+            age: self.age,
+            time_since_last_improvement: self.time_since_last_improvement,
+            penalized: penalized,
+            mean_nodes: self.organisms.iter().map(|o| o.genome.num_nodes() as f64).fold(0.0, |x,y| x+y) /
+                num_organisms,
+            mean_links: self.organisms.iter().map(|o| o.genome.num_links() as f64).fold(0.0, |x,y| x+y) /
+                num_organisms,
+        }
     }
 
     /// Before reproducing, delete the lowest performing members of the species -
@@ -179,10 +471,35 @@ impl Species {
         }
     }
 
+    /// Makes an organism of an offspring's genome. The problems of a malformed genome are added to `repairs`.
+    /// A genome that cannot be repaired is replaced by the genome of the species champion.
+    fn offspring(&self, genome: genes::Genome, repairs: &mut Vec<genes::Problem>) -> Organism {
+        match Organism::new_repaired(genome) {
+            Ok((organism, problems)) => {
+                repairs.extend(problems);
+                organism
+            },
+            Err(problems) => {
+                repairs.extend(problems);
+                Organism {
+                    fitness: 0.0,
+                    adj_fitness: 0.0,
+                    expected_offspring: 0.0,
+                    .. self.best_organism().clone()
+                }
+            }
+        }
+    }
+
+    /// `all_species` gives the elites of every species in the population, for interspecies mating.
+    /// The problems of offspring genomes that had to be repaired are added to `repairs`.
     pub fn reproduce<R: rand::Rng>(&self,
+                                   all_species: &[Species],
                                    mutation_settings: &mutation::Settings,
+                                   operators: &mating::Operators,
                                    rng: &mut R,
-                                   mutation_state: &mut mutation::State) -> Vec<Organism> {
+                                   mutation_state: &mut mutation::State,
+                                   repairs: &mut Vec<genes::Problem>) -> Vec<Organism> {
         assert!(self.expected_offspring > 0);
         assert!(self.organisms.len() > 0, "Empty species cannot reproduce");
 
@@ -197,10 +514,10 @@ impl Species {
             if i > 0 {
                 let mut genome = self.best_genome.clone();
                 mutation::change_link_weights_standard(&mut genome, rng, 1.0,
-                                                       mutation_settings.change_link_weights_power);
-                offspring.push(Organism::new(&genome));
+                                                       &mutation_settings.weight_change());
+                offspring.push(self.offspring(genome, repairs));
             } else {
-                offspring.push(Organism::new(&self.best_genome));
+                offspring.push(self.offspring(self.best_genome.clone(), repairs));
             }
         }
 
@@ -213,23 +530,29 @@ impl Species {
                 let mut new_genome = organism.genome.clone();
                 mutation::mutate(&mut new_genome, mutation_settings, rng, mutation_state);
 
-                offspring.push(Organism::new(&new_genome));
+                offspring.push(self.offspring(new_genome, repairs));
             } else {
-                // Random parents
-                let (parent_a, parent_b) = if rng.next_f64() < mutation_settings.interspecies_mating_prob {
-                    // TODO: interspecies mating
-                    (&self.organisms[rng.gen_range(0, self.organisms.len())],
-                     &self.organisms[rng.gen_range(0, self.organisms.len())])
-                } else {
-                    (&self.organisms[rng.gen_range(0, self.organisms.len())],
-                     &self.organisms[rng.gen_range(0, self.organisms.len())])
+                // Random parents, the second one possibly from another species
+                let parent_a = &self.organisms[rng.gen_range(0, self.organisms.len())];
+                let interspecies_mate =
+                    if rng.next_f64() < mutation_settings.interspecies_mating_prob {
+                        choose_interspecies_mate(rng, all_species, self.id,
+                                                 mutation_settings.interspecies_mate_selection)
+                    } else {
+                        None
+                    };
+                let parent_b = match interspecies_mate {
+                    Some(organism) => organism,
+                    None => &self.organisms[rng.gen_range(0, self.organisms.len())]
                 };
 
+                let equal_fitness = parent_a.fitness == parent_b.fitness;
                 let mut new_genome =
-                    if parent_a.fitness >= parent_b.fitness {
-                        mating::multipoint(rng, &parent_a.genome, &parent_b.genome)
+                    if mating::is_first_better(rng, parent_a.fitness, &parent_a.genome,
+                                               parent_b.fitness, &parent_b.genome) {
+                        operators.crossover(rng, &parent_a.genome, &parent_b.genome, equal_fitness)
                     } else {
-                        mating::multipoint(rng, &parent_b.genome, &parent_a.genome)
+                        operators.crossover(rng, &parent_b.genome, &parent_a.genome, equal_fitness)
                     };
 
                 // Mutate the offspring's genome according to some probability,
@@ -241,7 +564,7 @@ impl Species {
                     mutation::mutate(&mut new_genome, mutation_settings, rng, mutation_state);     
                 }
 
-                offspring.push(Organism::new(&new_genome));
+                offspring.push(self.offspring(new_genome, repairs));
             }
         }
 
@@ -251,23 +574,63 @@ impl Species {
     }
 }
 
+/// Checks each group of settings, and that they work for a population of `total_population` organisms
+pub fn validate_settings(settings: &Settings,
+                         mutation_settings: &mutation::Settings,
+                         compat_coefficients: &genes::CompatCoefficients,
+                         total_population: usize) -> Vec<validation::InvalidSetting> {
+    let mut invalid = vec![];
+
+    invalid.extend(settings.validate().into_iter().map(|setting| setting.in_group("population")));
+    invalid.extend(mutation_settings.validate().into_iter().map(|setting| setting.in_group("mutation")));
+    invalid.extend(compat_coefficients.validate().into_iter().map(|setting| setting.in_group("compat")));
+
+    if total_population == 0 {
+        invalid.push(validation::InvalidSetting::new("population_size", "there must be at least one organism".to_string()));
+    }
+
+    if let Some(target_num) = settings.target_num_species {
+        if target_num > total_population {
+            invalid.push(validation::InvalidSetting::new("population.target_num_species", format!(
+                "{} species cannot be reached with {} organisms", target_num, total_population)));
+        }
+    }
+
+    invalid
+}
+
 impl Population {
+    /// Fails if the settings do not work together
     pub fn from_initial_genome<R: rand::Rng>(rng: &mut R,
                                              settings: &Settings,
                                              mutation_settings: &mutation::Settings,
                                              compat_coefficients: &genes::CompatCoefficients,
                                              genome: &genes::Genome,
-                                             total_population: usize) -> Population {
-        assert!(genome.nodes.len() > 0, "Cannot start with empty genome");
+                                             total_population: usize) -> Result<Population, Error> {
+        let invalid = validate_settings(settings, mutation_settings, compat_coefficients, total_population);
+        if !invalid.is_empty() {
+            return Err(Error::Settings(invalid));
+        }
+
+        if let Err(problems) = Organism::new(genome) {
+            return Err(Error::InitialGenome(problems));
+        }
 
         let mut organisms = Vec::<Organism>::new();
 
+        // Initial weights are uniform in (-1,1), within the bounds of the settings
+        let initial_weights = mutation::WeightChange {
+            power: 1.0,
+            distribution: mutation::WeightDistribution::Uniform,
+            .. mutation_settings.weight_change()
+        };
+
         for _ in 0..total_population {
             // Generate completely random weights for each organism
             let mut new_genome = genome.clone();
-            mutation::change_link_weights_reset_all(&mut new_genome, rng, 1.0);
+            mutation::change_link_weights_reset_all(&mut new_genome, rng, &initial_weights);
 
-            organisms.push(Organism::new(&new_genome));
+            organisms.push(try!(Organism::new(&new_genome).map_err(Error::InitialGenome)));
         }
 
         // Start with one species containing all organisms
@@ -280,7 +643,7 @@ impl Population {
             }
         }
 
-        Population {
+        Ok(Population {
             settings: settings.clone(),
             mutation_settings: mutation_settings.clone(),
             compat_coefficients: compat_coefficients.clone(),
@@ -294,7 +657,37 @@ impl Population {
             generation: 0,
             highest_fitness: 0.0,
             time_since_last_improvement: 0,
-        }
+
+            phase: Phase::Complexifying,
+            complexity_ceiling: complexity(genome) +
+                                settings.phased_search.map(|phased_search| phased_search.complexity_threshold)
+                                                      .unwrap_or(0.0),
+            lowest_complexity: complexity(genome),
+            time_since_complexity_fell: 0,
+        })
+    }
+
+    /// Writes a checkpoint from which evolution can be resumed with `Checkpoint::load`.
+    /// `experiment_state` is given by `Experiment::save_state`.
+    pub fn save(&self, path: &Path, seed: usize, experiment_state: Json) -> Result<(), genes::Error> {
+        let mut file = BTreeMap::new();
+        file.insert("version".to_string(), CHECKPOINT_VERSION.to_json());
+        file.insert("population".to_string(), try!(parse::json(&try!(json::encode(&self))).map_err(genes::Error::Parse)));
+        file.insert("seed".to_string(), seed.to_json());
+        file.insert("experiment".to_string(), experiment_state);
+
+        // Write a new file and rename it over the old one, so that a crash during the write
+        // leaves the previous checkpoint intact
+        let mut temp_name = path.file_name().map(|name| name.to_os_string()).unwrap_or_default();
+        temp_name.push(".tmp");
+        let temp_path = path.with_file_name(temp_name);
+
+        let mut f = try!(File::create(&temp_path));
+        try!(f.write_all(Json::Object(file).to_string().as_bytes()));
+        try!(f.sync_all());
+        try!(fs::rename(&temp_path, path));
+
+        Ok(())
     }
 
     pub fn num_organisms(&self) -> usize {
@@ -344,22 +737,18 @@ impl Population {
         }
 
         // No matching species found - create a new one
-        println!("Creating species {}", self.species_counter);
-
         self.species.push(Species::new(self.species_counter, vec![organism]));
         self.species_counter += 1;
     }
 
     /// Allot number of offspring for each species.
-    fn allot_offspring(&mut self) {
+    /// Returns true if the population stagnated and only the best two species get offspring.
+    fn allot_offspring(&mut self) -> bool {
         let total_population = self.num_organisms();
 
         // If we have population-wide stagnation, give all the offspring to the best two species
         if self.settings.dropoff_age.is_some() &&
            self.time_since_last_improvement >= self.settings.dropoff_age.unwrap() {
-            println!("No improvement for {} epochs, keeping only the first two species",
-                     self.time_since_last_improvement);
-
             self.time_since_last_improvement = 0;
 
             // Create a sorted list of species indicies, sort by unmodified fitness of best organism
@@ -395,16 +784,11 @@ impl Population {
                 self.species[best_index].time_since_last_improvement = 0;
             }
 
-            return;
+            return true;
         }
 
         // Otherwise, distribute offspring among species according to the adjusted fitness
         let average_adj_fitness = self.average_adj_fitness();
-        println!("Average fitness: {}", average_adj_fitness);
-
-        let total_average_adj_fitness = self.species.iter().map(|species| species.average_adj_fitness())
-                                            .fold(0.0, |x,y| x+y);
-        println!("Total average fitness: {}", total_average_adj_fitness);
 
         // Here the isue is that if we just rounded down each species' expected offspring to get whole numbers,
         // we wouldn't necessarily reach `total_population` again. For this reason, we carry around
@@ -441,11 +825,88 @@ impl Population {
 
             self.species[best_species.unwrap()].expected_offspring += total_population - expected_offspring;
         }
+
+        false
+    }
+
+    /// Mean compatibility distance between the best genomes of all pairs of species
+    fn diversity(&self) -> f64 {
+        let mut sum_distance = 0.0;
+        let mut num_pairs = 0;
+
+        for i in 0..self.species.len() {
+            for j in i+1..self.species.len() {
+                sum_distance += genes::compatibility(&self.compat_coefficients,
+                                                     &self.species[i].best_genome,
+                                                     &self.species[j].best_genome);
+                num_pairs += 1;
+            }
+        }
+
+        if num_pairs > 0 {
+            sum_distance / num_pairs as f64
+        } else {
+            0.0
+        }
+    }
+
+    fn mean_complexity(&self) -> f64 {
+        let organisms = self.species.iter().flat_map(|species| species.organisms.iter()).collect::<Vec<_>>();
+
+        organisms.iter().map(|organism| complexity(&organism.genome)).fold(0.0, |x,y| x+y) /
+            organisms.len() as f64
+    }
+
+    /// Starts pruning when the mean complexity passes the ceiling,
+    /// and starts growing again when it has stopped falling
+    fn update_phase(&mut self, mean_complexity: f64) {
+        let phased_search = match self.settings.phased_search {
+            Some(phased_search) => phased_search,
+            None => return
+        };
+
+        match self.phase {
+            Phase::Complexifying => {
+                if mean_complexity > self.complexity_ceiling {
+                    self.phase = Phase::Simplifying;
+                    self.lowest_complexity = mean_complexity;
+                    self.time_since_complexity_fell = 0;
+                }
+            },
+            Phase::Simplifying => {
+                if mean_complexity < self.lowest_complexity {
+                    self.lowest_complexity = mean_complexity;
+                    self.time_since_complexity_fell = 0;
+                } else {
+                    self.time_since_complexity_fell += 1;
+                }
+
+                if self.time_since_complexity_fell >= phased_search.stall_generations {
+                    self.phase = Phase::Complexifying;
+                    self.complexity_ceiling = mean_complexity + phased_search.complexity_threshold;
+                }
+            }
+        }
     }
 
+    /// The mutation settings for the current phase
+    fn phase_mutation_settings(&self) -> mutation::Settings {
+        match (self.phase, self.settings.phased_search) {
+            (Phase::Simplifying, Some(phased_search)) =>
+                mutation::Settings {
+                    new_node_prob: 0.0,
+                    new_link_prob: 0.0,
+                    delete_link_prob: phased_search.delete_link_prob,
+                    delete_node_prob: phased_search.delete_node_prob,
+                    .. self.mutation_settings
+                },
+            _ => self.mutation_settings
+        }
+    }
 
-    /// Create a new generation of organisms
-    pub fn epoch<R: rand::Rng>(&mut self, rng: &mut R) {
+
+    /// Create a new generation of organisms, returning statistics about the current one
+    pub fn epoch<R: rand::Rng>(&mut self, rng: &mut R) -> stats::Stats {
         let total_population = self.num_organisms();
 
         assert!(self.species.len() > 0);
@@ -457,14 +918,14 @@ impl Population {
             let target_num = self.settings.target_num_species.unwrap();
 
             if self.species.len() < target_num {
-                self.settings.compat_threshold -= 0.3;
+                self.settings.compat_threshold -= COMPAT_THRESHOLD_STEP;
             }
             if self.species.len() > target_num {
-                self.settings.compat_threshold += 0.3;
+                self.settings.compat_threshold += COMPAT_THRESHOLD_STEP;
             }
 
-            if self.settings.compat_threshold < 0.3 {
-                self.settings.compat_threshold = 0.3;
+            if self.settings.compat_threshold < MIN_COMPAT_THRESHOLD {
+                self.settings.compat_threshold = MIN_COMPAT_THRESHOLD;
             }
         }
 
@@ -482,42 +943,51 @@ impl Population {
             }
         }
 
+        // Take the fitness statistics before preparing the species changes fitness 0
+        let species_fitness = self.species.iter().map(|species| species.fitness_stats()).collect::<Vec<_>>();
+        let best_fitness = species_fitness.iter().map(|&(best, _)| best).fold(0.0, f64::max);
+        let mean_fitness = self.species.iter()
+                                       .flat_map(|species| species.organisms.iter())
+                                       .map(|o| o.fitness)
+                                       .fold(0.0, |x,y| x+y) / total_population as f64;
+
+        let mut penalized = vec![];
         for species in self.species.iter_mut() {
-            species.prepare_for_epoch(self.settings.dropoff_age);
+            penalized.push(species.prepare_for_epoch(self.settings.dropoff_age));
         }
 
-        self.allot_offspring();
-
-        // Debug species
-        {
-            let mut species_sorted = (0..self.species.len()).collect::<Vec<_>>();
-            species_sorted.sort_by(
-                |a, b| self.species[*b].best_organism().fitness
-                           .partial_cmp(&self.species[*a].best_organism().fitness)
-                           .unwrap_or(Ordering::Equal));
-
-            for i in species_sorted.iter() {
-                let s = &self.species[*i];
-
-                println!("S{}: best {}, size {}->{}, adj fit {}, nodes {}",
-                         s.id, s.best_organism().fitness, s.organisms.len(), s.expected_offspring,
-                         s.average_adj_fitness(), 
-                         s.organisms.iter().map(|o| o.genome.nodes.len() as f64).fold(0.0, |x,y| x+y) / s.organisms.len() as f64);
-            }
-
-            /*println!("Compatibilities: ");
-            for i in species_sorted.iter() {
-                for j in species_sorted.iter() {
-                    print!("{:.1} ", genes::compatibility(&self.compat_coefficients, &self.species[*i].best_organism().genome, &self.species[*j].best_organism().genome));
-                }
-                println!("");
-            }*/
-        }
+        let stagnated = self.allot_offspring();
+
+        let mean_complexity = self.mean_complexity();
+        self.update_phase(mean_complexity);
+
+        let organisms = self.species.iter().flat_map(|species| species.organisms.iter()).collect::<Vec<_>>();
+        let mean = |f: &Fn(&Organism) -> f64| organisms.iter().map(|o| f(o)).fold(0.0, |x,y| x+y) /
+                                               total_population as f64;
+
+        let mut stats = stats::Stats {
+            generation: self.generation,
+            num_organisms: total_population,
+            best_fitness: best_fitness,
+            mean_fitness: mean_fitness,
+            highest_fitness: self.highest_fitness,
+            time_since_last_improvement: self.time_since_last_improvement,
+            stagnated: stagnated,
+            mean_nodes: mean(&|o| o.genome.num_nodes() as f64),
+            mean_links: mean(&|o| o.genome.num_links() as f64),
+            compat_threshold: self.settings.compat_threshold,
+            diversity: self.diversity(),
+            phase: self.settings.phased_search.map(|_| self.phase),
+            mean_complexity: mean_complexity,
+            complexity_ceiling: self.settings.phased_search.map(|_| self.complexity_ceiling),
+            species: self.species.iter().zip(penalized.into_iter()).zip(species_fitness.into_iter())
+                                 .map(|((species, penalized), fitness)| species.stats(penalized, fitness))
+                                 .collect(),
+            new_species: vec![],
+            extinct_species: vec![],
+            repairs: vec![],
+        };
 
-        println!("Highest fitness: {}, time since last improvement: {}",
-                 self.highest_fitness, self.time_since_last_improvement);
-        println!("Num species: {}, threshold: {}", self.species.len(), self.settings.compat_threshold);
-        
         // Only allow the elite of each species to reproduce
         for species in self.species.iter_mut() {
             species.prune_to_elite(self.settings.survival_threshold);
@@ -533,9 +1003,12 @@ impl Population {
         };
 
         // Reproduce
+        let mutation_settings = self.phase_mutation_settings();
+        let operators = mating::Operators::new(&mutation_settings);
         for species in self.species.iter() {
             if species.expected_offspring > 0 {
-                offspring.extend(species.reproduce(&self.mutation_settings, rng, &mut mutation_state));
+                offspring.extend(species.reproduce(&self.species, &mutation_settings, &operators,
+                                                   rng, &mut mutation_state, &mut stats.repairs));
             }
         }
 
@@ -546,21 +1019,421 @@ impl Population {
             species.organisms.clear(); 
         }
 
+        let species_counter = self.species_counter;
+
         for organism in offspring.iter() {
             self.insert_organism(organism.clone());
         }
 
+        stats.new_species = (species_counter..self.species_counter).collect();
+
         // Delete any species that is now empty
-        for species in self.species.iter() {
-            if species.organisms.len() == 0 {
-                println!("Species {} empty", species.id);
-            }
-        }
+        stats.extinct_species = self.species.iter()
+                                            .filter(|species| species.organisms.len() == 0)
+                                            .map(|species| species.id)
+                                            .collect();
         self.species.retain(|species| species.organisms.len() > 0);
 
         // Check that we have the same number of organisms as before this epoch
         assert_eq!(self.species.iter().map(|species| species.organisms.len()).fold(0, |x,y| x+y), total_population);
 
         self.generation += 1;
+
+        stats
+    }
+}
+
+impl Checkpoint {
+    pub fn load(path: &Path) -> Result<Checkpoint, Error> {
+        let mut f = try!(File::open(path));
+        let mut s = String::new();
+        try!(f.read_to_string(&mut s));
+
+        Checkpoint::from_json_str(&s)
+    }
+
+    /// Parses a checkpoint in the format written by `Population::save`
+    pub fn from_json_str(s: &str) -> Result<Checkpoint, Error> {
+        let file = try!(parse::json(s).map_err(genes::Error::Parse));
+
+        let version = try!(file.find("version")
+                               .and_then(|version| version.as_u64())
+                               .ok_or(genes::Error::Parse("missing version".to_string())));
+        if version > CHECKPOINT_VERSION {
+            return Err(Error::File(genes::Error::UnsupportedVersion(version)));
+        }
+
+        let population_json = try!(file.find("population")
+                                       .ok_or(genes::Error::Parse("missing population".to_string()))).clone();
+        let seed = try!(file.find("seed")
+                            .and_then(|seed| seed.as_u64())
+                            .ok_or(genes::Error::Parse("missing seed".to_string())));
+        let experiment_state = try!(file.find("experiment")
+                                        .ok_or(genes::Error::Parse("missing experiment state".to_string()))).clone();
+
+        let population: Population = try!(Decodable::decode(&mut json::Decoder::new(population_json)));
+
+        if population.species.iter().any(|species| species.organisms.is_empty()) {
+            return Err(Error::File(genes::Error::Parse("empty species".to_string())));
+        }
+        if population.num_organisms() == 0 {
+            return Err(Error::File(genes::Error::Parse("no organisms".to_string())));
+        }
+
+        let invalid = validate_settings(&population.settings,
+                                        &population.mutation_settings,
+                                        &population.compat_coefficients,
+                                        population.num_organisms());
+        if !invalid.is_empty() {
+            return Err(Error::Settings(invalid));
+        }
+
+        Ok(Checkpoint {
+            population: population,
+            seed: seed as usize,
+            experiment_state: experiment_state,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use genes;
+    use genes::fixtures;
+    use super::*;
+
+    #[test]
+    fn organism_rejects_malformed_genome() {
+        let mut genome = genes::Genome::initial_genome(2, 1, 2, true);
+        let duplicate = genome.nodes[0];
+        genome.nodes.push(duplicate);
+        let genome = genes::Genome::from_genes(genome.nodes, genome.links);
+
+        assert_eq!(Organism::new(&genome).err(), Some(vec![genes::Problem::DuplicateNodeId(0)]));
+
+        let (organism, problems) = Organism::new_repaired(genome).unwrap();
+        assert_eq!(organism.genome.nodes.len(), 4);
+        assert_eq!(problems, vec![genes::Problem::DuplicateNodeId(0)]);
+    }
+
+    #[test]
+    fn empty_initial_genome_is_rejected() {
+        let mut rng: rand::StdRng = rand::SeedableRng::from_seed(&[3usize][..]);
+
+        match Population::from_initial_genome(&mut rng, &STANDARD_SETTINGS, &mutation::STANDARD_SETTINGS,
+                                              &genes::STANDARD_COMPAT_COEFFICIENTS, &genes::Genome::new(), 50) {
+            Err(Error::InitialGenome(problems)) =>
+                assert_eq!(problems, vec![genes::Problem::NoInputs, genes::Problem::NoOutputs]),
+            _ => panic!("expected malformed initial genome")
+        }
+    }
+
+    fn population() -> Population {
+        let mut rng: rand::StdRng = rand::SeedableRng::from_seed(&[3usize][..]);
+
+        Population::from_initial_genome(&mut rng, &STANDARD_SETTINGS, &mutation::STANDARD_SETTINGS,
+                                        &genes::STANDARD_COMPAT_COEFFICIENTS,
+                                        &genes::Genome::initial_genome(2, 1, 2, true), 50).unwrap()
+    }
+
+    /// Prunes after growing by 10 over the initial complexity of 7, until no fall for 3 generations
+    fn phased_population() -> Population {
+        let mut rng: rand::StdRng = rand::SeedableRng::from_seed(&[3usize][..]);
+        let settings = Settings {
+            phased_search: Some(PhasedSearch {
+                complexity_threshold: 10.0,
+                stall_generations: 3,
+                delete_link_prob: 0.2,
+                delete_node_prob: 0.1,
+            }),
+            .. STANDARD_SETTINGS
+        };
+        let mutation_settings = mutation::Settings {
+            new_node_prob: 0.03,
+            new_link_prob: 0.05,
+            delete_link_prob: 0.0,
+            delete_node_prob: 0.0,
+            .. mutation::STANDARD_SETTINGS
+        };
+
+        Population::from_initial_genome(&mut rng, &settings, &mutation_settings,
+                                        &genes::STANDARD_COMPAT_COEFFICIENTS,
+                                        &genes::Genome::initial_genome(2, 1, 2, true), 50).unwrap()
+    }
+
+    fn checkpoint_json(version: u64, population_json: Json) -> String {
+        let mut file = BTreeMap::new();
+        file.insert("version".to_string(), version.to_json());
+        file.insert("population".to_string(), population_json);
+        file.insert("seed".to_string(), 1usize.to_json());
+        file.insert("experiment".to_string(), Json::Null);
+
+        Json::Object(file).to_string()
+    }
+
+    #[test]
+    fn checkpoint_keeps_population() {
+        let population = population();
+        let population_json = parse::json(&json::encode(&population).unwrap()).unwrap();
+
+        let loaded = Checkpoint::from_json_str(&checkpoint_json(CHECKPOINT_VERSION, population_json)).unwrap();
+
+        assert_eq!(json::encode(&loaded.population).unwrap(), json::encode(&population).unwrap());
+    }
+
+    #[test]
+    fn checkpoint_keeps_seed_and_experiment_state() {
+        let path = ::std::env::temp_dir().join(format!("neat-checkpoint-test-{}.json", ::std::process::id()));
+        let state = Json::Array(vec![Json::F64(0.3), Json::String("state".to_string())]);
+
+        population().save(&path, 1234, state.clone()).unwrap();
+        let loaded = Checkpoint::load(&path).unwrap();
+        ::std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.seed, 1234);
+        assert_eq!(loaded.experiment_state, state);
+    }
+
+    #[test]
+    fn save_replaces_checkpoint() {
+        let dir = ::std::env::temp_dir();
+        let path = dir.join(format!("neat-checkpoint-replace-test-{}.json", ::std::process::id()));
+        let temp_path = dir.join(format!("neat-checkpoint-replace-test-{}.json.tmp", ::std::process::id()));
+
+        population().save(&path, 1, Json::Null).unwrap();
+        population().save(&path, 2, Json::Null).unwrap();
+        let loaded = Checkpoint::load(&path).unwrap();
+        ::std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.seed, 2);
+        assert!(!temp_path.exists());
+    }
+
+    #[test]
+    fn newer_checkpoint_is_rejected() {
+        let population_json = parse::json(&json::encode(&population()).unwrap()).unwrap();
+
+        match Checkpoint::from_json_str(&checkpoint_json(CHECKPOINT_VERSION + 1, population_json)) {
+            Err(Error::File(genes::Error::UnsupportedVersion(version))) => assert_eq!(version, CHECKPOINT_VERSION + 1),
+            _ => panic!("expected unsupported version")
+        }
+    }
+
+    #[test]
+    fn phase_switches_to_simplifying_above_ceiling() {
+        let mut population = phased_population();
+        assert_eq!(population.mean_complexity(), 7.0);
+
+        population.update_phase(12.0);
+        population.update_phase(17.0);
+        assert_eq!(population.phase, Phase::Complexifying);
+
+        population.update_phase(17.5);
+        assert_eq!(population.phase, Phase::Simplifying);
+    }
+
+    #[test]
+    fn phase_switches_back_after_plateau() {
+        let mut population = phased_population();
+        population.update_phase(20.0);
+
+        // Falling complexity keeps pruning going
+        for &mean_complexity in [18.0, 16.0, 16.5, 16.0, 14.0].iter() {
+            population.update_phase(mean_complexity);
+            assert_eq!(population.phase, Phase::Simplifying);
+        }
+
+        population.update_phase(14.0);
+        population.update_phase(15.0);
+        assert_eq!(population.phase, Phase::Simplifying);
+
+        population.update_phase(14.5);
+        assert_eq!(population.phase, Phase::Complexifying);
+
+        // The next ceiling is relative to the complexity reached
+        population.update_phase(24.0);
+        assert_eq!(population.phase, Phase::Complexifying);
+        population.update_phase(25.0);
+        assert_eq!(population.phase, Phase::Simplifying);
+    }
+
+    #[test]
+    fn pruning_phase_only_deletes() {
+        let mut population = phased_population();
+
+        let settings = population.phase_mutation_settings();
+        assert_eq!((settings.new_node_prob, settings.new_link_prob), (0.03, 0.05));
+        assert_eq!((settings.delete_link_prob, settings.delete_node_prob), (0.0, 0.0));
+
+        population.update_phase(20.0);
+
+        let settings = population.phase_mutation_settings();
+        assert_eq!((settings.new_node_prob, settings.new_link_prob), (0.0, 0.0));
+        assert_eq!((settings.delete_link_prob, settings.delete_node_prob), (0.2, 0.1));
+        assert_eq!(settings.change_link_weights_prob, mutation::STANDARD_SETTINGS.change_link_weights_prob);
+    }
+
+    /// Species 1 with fitness 5, species 2 with zero fitness and species 3 with fitness 1 and 2
+    fn mating_species(zero_fitness: bool) -> Vec<Species> {
+        let organism = |fitness| Organism {
+            fitness: if zero_fitness { 0.0 } else { fitness },
+            .. Organism::new(&genes::Genome::initial_genome(2, 1, 2, true)).unwrap()
+        };
+
+        vec![Species::new(1, vec![organism(5.0), organism(5.0)]),
+             Species::new(2, vec![organism(0.0), organism(0.0), organism(0.0)]),
+             Species::new(3, vec![organism(1.0), organism(2.0)])]
+    }
+
+    /// Id of the species that contains the organism
+    fn species_of(all_species: &[Species], organism: &Organism) -> usize {
+        all_species.iter()
+                   .find(|species| species.organisms.iter().any(|other| other as *const _ == organism as *const _))
+                   .unwrap()
+                   .id
+    }
+
+    /// How often each species is chosen as mate for species 1 in 1000 tries
+    fn mate_counts(all_species: &[Species], selection: mutation::MateSelection) -> BTreeMap<usize, usize> {
+        let mut rng: rand::StdRng = rand::SeedableRng::from_seed(&[11usize][..]);
+        let mut counts = BTreeMap::new();
+
+        for _ in 0..1000 {
+            let mate = choose_interspecies_mate(&mut rng, all_species, 1, selection).unwrap();
+            *counts.entry(species_of(all_species, mate)).or_insert(0) += 1;
+        }
+
+        counts
+    }
+
+    #[test]
+    fn random_mate_comes_from_any_other_species() {
+        let counts = mate_counts(&mating_species(false), mutation::MateSelection::Random);
+
+        assert_eq!(counts.keys().cloned().collect::<Vec<_>>(), vec![2, 3]);
+        assert!(counts[&2] > 400 && counts[&3] > 400, "{:?}", counts);
+    }
+
+    #[test]
+    fn fitness_weighted_mate_has_fitness() {
+        let counts = mate_counts(&mating_species(false), mutation::MateSelection::FitnessWeighted);
+
+        assert_eq!(counts.keys().cloned().collect::<Vec<_>>(), vec![3]);
+    }
+
+    #[test]
+    fn fitness_weighted_mate_without_any_fitness() {
+        let counts = mate_counts(&mating_species(true), mutation::MateSelection::FitnessWeighted);
+
+        // Species 2 has more organisms
+        assert_eq!(counts.keys().cloned().collect::<Vec<_>>(), vec![2, 3]);
+        assert!(counts[&2] > counts[&3], "{:?}", counts);
+    }
+
+    #[test]
+    fn no_mate_without_other_species() {
+        let mut rng: rand::StdRng = rand::SeedableRng::from_seed(&[11usize][..]);
+        let all_species = &mating_species(false)[..1];
+
+        assert!(choose_interspecies_mate(&mut rng, all_species, 1, mutation::MateSelection::Random).is_none());
+        assert!(choose_interspecies_mate(&mut rng, all_species, 1, mutation::MateSelection::FitnessWeighted).is_none());
+    }
+
+    #[test]
+    fn epoch_reports_generation_stats() {
+        let small = genes::Genome::initial_genome(2, 1, 2, true);
+        let mut large = small.clone();
+        large.add_node(genes::Node {
+            id: 4,
+            node_type: genes::NodeType::Hidden,
+            activation: genes::ActivationFunction::Tanh,
+        });
+        large.add_link(fixtures::link(0, 4, 3));
+        large.add_link(fixtures::link(4, 3, 4));
+
+        let organism = |genome: &genes::Genome, fitness| Organism { fitness: fitness, .. Organism::new(genome).unwrap() };
+
+        // 20 organisms with 4 nodes and 3 links, 30 with 5 nodes and 5 links
+        let mut population = population();
+        population.species = vec![Species::new(1, (0..20).map(|_| organism(&small, 1.0)).collect()),
+                                  Species::new(2, (0..30).map(|i| organism(&large, if i == 7 { 4.0 } else { 2.0 }))
+                                                         .collect())];
+        population.species_counter = 3;
+        population.node_counter = 5;
+        population.innovation_counter = 5;
+
+        let mut rng: rand::StdRng = rand::SeedableRng::from_seed(&[3usize][..]);
+        let stats = population.epoch(&mut rng);
+
+        assert_eq!(stats.generation, 0);
+        assert_eq!(stats.num_organisms, 50);
+        assert_eq!(stats.species.len(), 2);
+        assert_eq!(stats.best_fitness, 4.0);
+        assert!((stats.mean_fitness - (20.0 * 1.0 + 29.0 * 2.0 + 4.0) / 50.0).abs() < 1e-9);
+        assert!((stats.mean_nodes - 4.6).abs() < 1e-9);
+        assert!((stats.mean_links - 4.2).abs() < 1e-9);
+        assert!((stats.mean_complexity - 8.8).abs() < 1e-9);
+
+        assert_eq!(stats.species.iter().map(|species| (species.id, species.size)).collect::<Vec<_>>(),
+                   vec![(1, 20), (2, 30)]);
+        assert_eq!(stats.species[1].best_fitness, 4.0);
+        assert_eq!(population.generation, 1);
+    }
+
+    #[test]
+    fn epoch_reports_fitness_as_evaluated() {
+        let genome = genes::Genome::initial_genome(2, 1, 2, true);
+        let organism = |fitness| Organism { fitness: fitness, .. Organism::new(&genome).unwrap() };
+
+        let mut population = population();
+        population.species = vec![Species::new(1, (0..20).map(|_| organism(0.0)).collect()),
+                                  Species::new(2, (0..30).map(|i| organism(if i == 7 { 4.0 } else { 2.0 }))
+                                                         .collect())];
+        population.species_counter = 3;
+
+        let mut rng: rand::StdRng = rand::SeedableRng::from_seed(&[3usize][..]);
+        let stats = population.epoch(&mut rng);
+
+        // Fitness 0 is raised for fitness sharing, but not in the statistics
+        assert_eq!(stats.best_fitness, 4.0);
+        assert!((stats.mean_fitness - (29.0 * 2.0 + 4.0) / 50.0).abs() < 1e-9);
+        assert_eq!(stats.species[0].best_fitness, 0.0);
+        assert_eq!(stats.species[0].mean_fitness, 0.0);
+        assert!((stats.species[1].mean_fitness - (29.0 * 2.0 + 4.0) / 30.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn reproduction_needs_no_repairs() {
+        // Every structural mutation and crossover operator, at high rates
+        let mutation_settings = mutation::Settings {
+            new_node_prob: 0.2,
+            new_link_prob: 0.3,
+            delete_link_prob: 0.2,
+            delete_node_prob: 0.2,
+            toggle_enable_prob: 0.2,
+            interspecies_mating_prob: 0.2,
+            multipoint_prob: 0.4,
+            multipoint_avg_prob: 0.3,
+            singlepoint_prob: 0.3,
+            .. mutation::STANDARD_SETTINGS
+        };
+        let mut rng: rand::StdRng = rand::SeedableRng::from_seed(&[5usize][..]);
+        let mut population = Population::from_initial_genome(&mut rng, &STANDARD_SETTINGS, &mutation_settings,
+                                                              &genes::STANDARD_COMPAT_COEFFICIENTS,
+                                                              &genes::Genome::initial_genome(3, 2, 3, true), 60)
+                                                              .unwrap();
+
+        for _ in 0..30 {
+            for species in population.species.iter_mut() {
+                for organism in species.organisms.iter_mut() {
+                    organism.fitness = rng.gen_range(0.1, 1.0);
+                }
+            }
+
+            let stats = population.epoch(&mut rng);
+            assert!(stats.repairs.is_empty(), "{:?}", stats.repairs);
+        }
     }
 }
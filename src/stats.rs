@@ -0,0 +1,366 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::prelude::*;
+use std::io;
+use std::path::Path;
+
+use rustc_serialize::json::{ToJson, Json};
+
+use genes;
+use pop;
+
+/// Summary of one species in a generation, taken before reproduction
+#[derive(Clone, Debug)]
+pub struct SpeciesStats {
+    pub id: usize,
+    pub size: usize,
+    pub expected_offspring: usize,
+
+    pub best_fitness: f64,
+    pub mean_fitness: f64,
+
+    pub age: usize,
+    pub time_since_last_improvement: usize,
+    pub penalized: bool, // Adjusted fitness was reduced because of stagnation
+
+    pub mean_nodes: f64,
+    pub mean_links: f64, // Enabled links only
+}
+
+/// Summary of one generation of a population, returned by `Population::epoch`
+#[derive(Clone, Debug)]
+pub struct Stats {
+    pub generation: usize,
+    pub num_organisms: usize,
+
+    pub best_fitness: f64,
+    pub mean_fitness: f64,
+    pub highest_fitness: f64, // Over all time
+    pub time_since_last_improvement: usize,
+    pub stagnated: bool, // Offspring went to the best two species only
+
+    pub mean_nodes: f64,
+    pub mean_links: f64,
+
+    pub compat_threshold: f64,
+    pub diversity: f64, // Mean compatibility distance between the best genomes of the species
+
+    pub phase: Option<pop::Phase>, // None if phased search is disabled
+    pub mean_complexity: f64, // Number of node and link genes
+    pub complexity_ceiling: Option<f64>, // Mean complexity at which the simplifying phase starts
+
+    pub species: Vec<SpeciesStats>,
+    pub new_species: Vec<usize>, // Ids of the species created by reproduction
+    pub extinct_species: Vec<usize>, // Ids of the species left without organisms
+
+    // Problems of the offspring genomes that had to be repaired. Reproduction should not cause any,
+    // so these point to a bug in mutation or mating.
+    pub repairs: Vec<genes::Problem>,
+}
+
+/// Receives the statistics of every generation, e.g. to print or store them
+pub trait Observer {
+    fn observe(&mut self, stats: &Stats) -> io::Result<()>;
+}
+
+/// Prints a summary of each generation to stdout
+pub struct Printer;
+
+impl Observer for Printer {
+    fn observe(&mut self, stats: &Stats) -> io::Result<()> {
+        let mut species = stats.species.iter().collect::<Vec<_>>();
+        species.sort_by(|a, b| b.best_fitness.partial_cmp(&a.best_fitness).unwrap_or(Ordering::Equal));
+
+        for s in species {
+            println!("S{}: best {}, size {}->{}, mean fit {}, nodes {}{}",
+                     s.id, s.best_fitness, s.size, s.expected_offspring, s.mean_fitness, s.mean_nodes,
+                     if s.penalized { ", penalized" } else { "" });
+        }
+
+        if stats.stagnated {
+            println!("No improvement for a long time, keeping only the first two species");
+        }
+
+        for id in stats.new_species.iter() {
+            println!("Creating species {}", id);
+        }
+        for id in stats.extinct_species.iter() {
+            println!("Species {} empty", id);
+        }
+
+        println!("Highest fitness: {}, time since last improvement: {}",
+                 stats.highest_fitness, stats.time_since_last_improvement);
+        println!("Num species: {}, threshold: {}, diversity: {}",
+                 stats.species.len(), stats.compat_threshold, stats.diversity);
+
+        if let (Some(phase), Some(ceiling)) = (stats.phase, stats.complexity_ceiling) {
+            println!("Phase: {}, mean complexity: {}, ceiling: {}", phase.name(), stats.mean_complexity, ceiling);
+        }
+
+        Ok(())
+    }
+}
+
+// Columns of the run logs, shared by the CSV and JSON logs
+fn generation_row(stats: &Stats) -> Vec<(&'static str, Json)> {
+    vec![("generation", stats.generation.to_json()),
+         ("num_organisms", stats.num_organisms.to_json()),
+         ("best_fitness", stats.best_fitness.to_json()),
+         ("mean_fitness", stats.mean_fitness.to_json()),
+         ("highest_fitness", stats.highest_fitness.to_json()),
+         ("time_since_last_improvement", stats.time_since_last_improvement.to_json()),
+         ("stagnated", stats.stagnated.to_json()),
+         ("mean_nodes", stats.mean_nodes.to_json()),
+         ("mean_links", stats.mean_links.to_json()),
+         ("num_species", stats.species.len().to_json()),
+         ("num_new_species", stats.new_species.len().to_json()),
+         ("num_extinct_species", stats.extinct_species.len().to_json()),
+         ("compat_threshold", stats.compat_threshold.to_json()),
+         ("diversity", stats.diversity.to_json()),
+         ("phase", stats.phase.map(|phase| phase.name().to_string()).to_json()),
+         ("mean_complexity", stats.mean_complexity.to_json()),
+         ("complexity_ceiling", stats.complexity_ceiling.to_json()),
+         ("num_repairs", stats.repairs.len().to_json())]
+}
+
+fn species_row(generation: usize, species: &SpeciesStats) -> Vec<(&'static str, Json)> {
+    vec![("generation", generation.to_json()),
+         ("id", species.id.to_json()),
+         ("size", species.size.to_json()),
+         ("expected_offspring", species.expected_offspring.to_json()),
+         ("best_fitness", species.best_fitness.to_json()),
+         ("mean_fitness", species.mean_fitness.to_json()),
+         ("age", species.age.to_json()),
+         ("time_since_last_improvement", species.time_since_last_improvement.to_json()),
+         ("penalized", species.penalized.to_json()),
+         ("mean_nodes", species.mean_nodes.to_json()),
+         ("mean_links", species.mean_links.to_json())]
+}
+
+/// How a log treats the rows already in its file
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogMode {
+    New, // Starts an empty log
+    Resume(usize), // Keeps the rows of the generations before the given one, which a resumed run does not repeat
+}
+
+/// Opens a log for appending, after removing the rows that `mode` does not keep.
+/// `row_generation` gives the generation of a row. If `has_header` is true, the first line is kept as the header.
+/// Other lines without a generation are dropped, such as a row cut off by a crash while it was written.
+/// Returns whether the file is empty.
+fn open_log(path: &Path, mode: LogMode, has_header: bool, row_generation: fn(&str) -> Option<usize>)
+            -> io::Result<(File, bool)> {
+    let kept = match mode {
+        LogMode::New => String::new(),
+        LogMode::Resume(generation) => {
+            let mut contents = String::new();
+
+            match File::open(path) {
+                Ok(mut f) => { try!(f.read_to_string(&mut contents)); },
+                Err(ref err) if err.kind() == io::ErrorKind::NotFound => (),
+                Err(err) => return Err(err)
+            }
+
+            contents.lines()
+                    .enumerate()
+                    .filter(|&(index, line)| (has_header && index == 0) ||
+                                             row_generation(line).map(|row| row < generation).unwrap_or(false))
+                    .map(|(_, line)| format!("{}\n", line))
+                    .collect()
+        }
+    };
+
+    let mut file = try!(OpenOptions::new().create(true).write(true).truncate(true).open(path));
+    try!(file.write_all(kept.as_bytes()));
+
+    Ok((file, kept.is_empty()))
+}
+
+/// The generation is the first value of a CSV row
+fn csv_row_generation(line: &str) -> Option<usize> {
+    line.split(',').next().and_then(|value| value.parse().ok())
+}
+
+fn json_row_generation(line: &str) -> Option<usize> {
+    Json::from_str(line).ok()
+                        .and_then(|row| row.find("generation").and_then(|generation| generation.as_u64()))
+                        .map(|generation| generation as usize)
+}
+
+/// Appends one row per generation to a CSV file, and one row per species and generation to another
+pub struct CsvLog {
+    generations: CsvFile,
+    species: CsvFile,
+}
+
+struct CsvFile {
+    file: File,
+    needs_header: bool, // The header is written with the first row
+}
+
+impl CsvFile {
+    fn open(path: &Path, mode: LogMode) -> io::Result<CsvFile> {
+        let (file, is_empty) = try!(open_log(path, mode, true, csv_row_generation));
+
+        Ok(CsvFile {
+            file: file,
+            needs_header: is_empty,
+        })
+    }
+
+    fn write_row(&mut self, row: &[(&'static str, Json)]) -> io::Result<()> {
+        if self.needs_header {
+            let names = row.iter().map(|&(name, _)| name).collect::<Vec<_>>();
+            try!(writeln!(self.file, "{}", names.join(",")));
+
+            self.needs_header = false;
+        }
+
+        let values = row.iter().map(|&(_, ref value)| csv_cell(value)).collect::<Vec<_>>();
+        writeln!(self.file, "{}", values.join(","))
+    }
+}
+
+/// Strings are written without quotes and missing values as empty cells
+fn csv_cell(value: &Json) -> String {
+    match *value {
+        Json::String(ref s) => s.clone(),
+        Json::Null => String::new(),
+        ref value => value.to_string()
+    }
+}
+
+impl CsvLog {
+    pub fn open(generations_path: &Path, species_path: &Path, mode: LogMode) -> io::Result<CsvLog> {
+        Ok(CsvLog {
+            generations: try!(CsvFile::open(generations_path, mode)),
+            species: try!(CsvFile::open(species_path, mode)),
+        })
+    }
+}
+
+impl Observer for CsvLog {
+    fn observe(&mut self, stats: &Stats) -> io::Result<()> {
+        try!(self.generations.write_row(&generation_row(stats)));
+
+        for species in stats.species.iter() {
+            try!(self.species.write_row(&species_row(stats.generation, species)));
+        }
+
+        Ok(())
+    }
+}
+
+/// Appends one JSON object per generation to a file, and one object per species and generation to another.
+/// Each object is on its own line.
+pub struct JsonLog {
+    generations: File,
+    species: File,
+}
+
+impl JsonLog {
+    pub fn open(generations_path: &Path, species_path: &Path, mode: LogMode) -> io::Result<JsonLog> {
+        let (generations, _) = try!(open_log(generations_path, mode, false, json_row_generation));
+        let (species, _) = try!(open_log(species_path, mode, false, json_row_generation));
+
+        Ok(JsonLog {
+            generations: generations,
+            species: species,
+        })
+    }
+}
+
+fn write_json_row(file: &mut File, row: Vec<(&'static str, Json)>) -> io::Result<()> {
+    let object = row.into_iter()
+                    .map(|(name, value)| (name.to_string(), value))
+                    .collect::<BTreeMap<_, _>>();
+    writeln!(file, "{}", Json::Object(object))
+}
+
+impl Observer for JsonLog {
+    fn observe(&mut self, stats: &Stats) -> io::Result<()> {
+        try!(write_json_row(&mut self.generations, generation_row(stats)));
+
+        for species in stats.species.iter() {
+            try!(write_json_row(&mut self.species, species_row(stats.generation, species)));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+    use std::io::prelude::*;
+
+    use super::*;
+
+    fn write(path: &Path, contents: &str) {
+        File::create(path).unwrap().write_all(contents.as_bytes()).unwrap();
+    }
+
+    fn read(path: &Path) -> String {
+        let mut s = String::new();
+        File::open(path).unwrap().read_to_string(&mut s).unwrap();
+        s
+    }
+
+    #[test]
+    fn resumed_logs_drop_repeated_generations() {
+        let dir = ::std::env::temp_dir();
+        let csv_path = dir.join(format!("neat-log-test-{}.csv", ::std::process::id()));
+        let json_path = dir.join(format!("neat-log-test-{}.jsonl", ::std::process::id()));
+
+        write(&csv_path, "generation,best_fitness\n0,1.5\n1,2.5\n2,3.5\n");
+        write(&json_path, "{\"best_fitness\":1.5,\"generation\":0}\n{\"best_fitness\":2.5,\"generation\":1}\n");
+
+        let (mut csv, is_empty) = open_log(&csv_path, LogMode::Resume(1), true, csv_row_generation).unwrap();
+        assert!(!is_empty);
+        writeln!(csv, "1,4.5").unwrap();
+        assert_eq!(read(&csv_path), "generation,best_fitness\n0,1.5\n1,4.5\n");
+
+        open_log(&json_path, LogMode::Resume(1), false, json_row_generation).unwrap();
+        assert_eq!(read(&json_path), "{\"best_fitness\":1.5,\"generation\":0}\n");
+
+        let (_, is_empty) = open_log(&csv_path, LogMode::New, true, csv_row_generation).unwrap();
+        assert!(is_empty);
+        assert_eq!(read(&csv_path), "");
+
+        fs::remove_file(&csv_path).unwrap();
+        fs::remove_file(&json_path).unwrap();
+    }
+
+    #[test]
+    fn resumed_logs_drop_rows_without_generation() {
+        let dir = ::std::env::temp_dir();
+        let csv_path = dir.join(format!("neat-log-cut-off-test-{}.csv", ::std::process::id()));
+        let json_path = dir.join(format!("neat-log-cut-off-test-{}.jsonl", ::std::process::id()));
+
+        // A crash while writing the last row
+        write(&json_path, "{\"best_fitness\":1.5,\"generation\":0}\n{\"best_fitness\":2.5,\"generation\":1}\n{\"best_fi");
+
+        let (mut json, _) = open_log(&json_path, LogMode::Resume(2), false, json_row_generation).unwrap();
+        writeln!(json, "{{\"best_fitness\":3.5,\"generation\":2}}").unwrap();
+        assert_eq!(read(&json_path), "{\"best_fitness\":1.5,\"generation\":0}\n{\"best_fitness\":2.5,\"generation\":1}\n\
+                                      {\"best_fitness\":3.5,\"generation\":2}\n");
+
+        // Only the first line of a CSV file is a header
+        write(&csv_path, "generation,best_fitness\n0,1.5\ngeneration,best_fitness\n");
+        open_log(&csv_path, LogMode::Resume(2), true, csv_row_generation).unwrap();
+        assert_eq!(read(&csv_path), "generation,best_fitness\n0,1.5\n");
+
+        fs::remove_file(&csv_path).unwrap();
+        fs::remove_file(&json_path).unwrap();
+    }
+
+    #[test]
+    fn csv_cells_are_plain() {
+        assert_eq!(csv_cell(&Json::String("simplifying".to_string())), "simplifying");
+        assert_eq!(csv_cell(&Json::Null), "");
+        assert_eq!(csv_cell(&Json::F64(2.5)), "2.5");
+        assert_eq!(csv_cell(&Json::U64(3)), "3");
+        assert_eq!(csv_cell(&Json::Boolean(true)), "true");
+    }
+}
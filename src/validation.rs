@@ -0,0 +1,38 @@
+use std::fmt;
+
+/// A setting whose value cannot work, with an explanation
+#[derive(Clone, Debug)]
+pub struct InvalidSetting {
+    pub field: String,
+    pub reason: String,
+}
+
+impl InvalidSetting {
+    pub fn new(field: &str, reason: String) -> InvalidSetting {
+        InvalidSetting {
+            field: field.to_string(),
+            reason: reason,
+        }
+    }
+
+    /// Puts the setting into a group, e.g. `mutation`
+    pub fn in_group(self, group: &str) -> InvalidSetting {
+        InvalidSetting {
+            field: format!("{}.{}", group, self.field),
+            reason: self.reason,
+        }
+    }
+}
+
+impl fmt::Display for InvalidSetting {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.reason)
+    }
+}
+
+/// Checks that `value` is in [0,1]
+pub fn check_prob(invalid: &mut Vec<InvalidSetting>, field: &str, value: f64) {
+    if !(value >= 0.0 && value <= 1.0) {
+        invalid.push(InvalidSetting::new(field, format!("{} is not a probability, it must be in [0,1]", value)));
+    }
+}